@@ -4,8 +4,12 @@
 //! Base CLI implementation.
 
 use crate::common::{PROJECT_AUTHOR, PROJECT_DESC, PROJECT_NAME, PROJECT_VERSION};
+use crate::config::Config;
+use crate::error::{Result, ToolErrorKind};
 use crate::subcommands::Subcommand;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::path::PathBuf;
 
 /// Struct representing the command-line interface of parsec-tool.
 #[derive(Debug, Parser)]
@@ -20,7 +24,155 @@ pub struct ParsecToolApp {
     #[structopt(short = 't', long = "timeout")]
     pub timeout: Option<u32>,
 
+    /// The application name used by the direct authenticator, which identifies the owner of the keys.
+    /// Will use "parsec-tool" if not specified. Ignored by the other authenticators.
+    #[structopt(long = "app-name", env = "PARSEC_TOOL_APP_NAME")]
+    pub app_name: Option<String>,
+
+    /// The endpoint of the Parsec service, for example unix:/run/parsec/parsec.sock. Overrides the
+    /// PARSEC_SERVICE_ENDPOINT environment variable.
+    #[structopt(
+        long = "endpoint",
+        alias = "socket",
+        env = "PARSEC_SERVICE_ENDPOINT",
+        value_parser = parse_endpoint
+    )]
+    pub endpoint: Option<String>,
+
+    /// The authenticator to use. Will use the first authenticator of the service supported by the
+    /// tool if not specified.
+    #[structopt(long = "auth", value_enum, env = "PARSEC_TOOL_AUTH")]
+    pub auth: Option<AuthMethod>,
+
+    /// The SPIFFE Workload API socket used by the JWT-SVID authenticator, for example
+    /// unix:/tmp/spire-agent/public/api.sock. Overrides the SPIFFE_ENDPOINT_SOCKET environment
+    /// variable.
+    #[structopt(
+        long = "spiffe-socket",
+        env = "SPIFFE_ENDPOINT_SOCKET",
+        value_parser = parse_spiffe_socket
+    )]
+    pub spiffe_socket: Option<String>,
+
+    /// The audience of the JWT-SVIDs fetched by the tool. Will use "parsec" if not specified.
+    #[structopt(long = "spiffe-audience", env = "PARSEC_TOOL_SPIFFE_AUDIENCE")]
+    pub spiffe_audience: Option<String>,
+
+    /// The output format of the commands printing information. Will use text if not specified.
+    #[structopt(long = "output-format", value_enum)]
+    pub output_format: Option<OutputFormat>,
+
+    /// The format of the error written on the standard error when a command fails. Will use text
+    /// if not specified.
+    #[structopt(long = "error-format", value_enum, env = "PARSEC_TOOL_ERROR_FORMAT")]
+    pub error_format: Option<OutputFormat>,
+
+    /// The profile of the configuration files to use. Will use the "default" profile, if defined,
+    /// if not specified.
+    #[structopt(long = "profile", env = "PARSEC_TOOL_PROFILE")]
+    pub profile: Option<String>,
+
+    /// The configuration file to read instead of /etc/parsec-tool.toml and
+    /// ~/.config/parsec-tool/config.toml.
+    #[structopt(long = "config", env = "PARSEC_TOOL_CONFIG")]
+    pub config: Option<PathBuf>,
+
     /// The subcommand -- e.g., ping.
     #[structopt(subcommand)]
     pub subcommand: Subcommand,
 }
+
+impl ParsecToolApp {
+    /// Fills the options not given on the command line or in the environment from the selected
+    /// profile of the configuration files.
+    pub fn apply_config(&mut self) -> Result<()> {
+        let config = Config::load(self.config.as_deref())?;
+        let profile = config.profile(self.profile.as_deref())?;
+
+        if self.endpoint.is_none() {
+            if let Some(endpoint) = profile.endpoint {
+                self.endpoint = Some(parse_endpoint(&endpoint).map_err(|e| {
+                    ToolErrorKind::InvalidData(format!(
+                        "wrong endpoint \"{}\" in the profile: {}",
+                        endpoint, e
+                    ))
+                })?);
+            }
+        }
+        if self.spiffe_socket.is_none() {
+            if let Some(socket) = profile.spiffe_socket {
+                self.spiffe_socket = Some(parse_spiffe_socket(&socket).map_err(|e| {
+                    ToolErrorKind::InvalidData(format!(
+                        "wrong SPIFFE socket \"{}\" in the profile: {}",
+                        socket, e
+                    ))
+                })?);
+            }
+        }
+        self.spiffe_audience = self.spiffe_audience.take().or(profile.spiffe_audience);
+        self.provider = self.provider.or(profile.provider);
+        self.timeout = self.timeout.or(profile.timeout);
+        self.app_name = self.app_name.take().or(profile.app_name);
+        self.auth = self.auth.or(profile.auth);
+        self.output_format = self.output_format.or(profile.output_format);
+        self.error_format = self.error_format.or(profile.error_format);
+        Ok(())
+    }
+}
+
+/// Audience of the JWT-SVIDs, expected by the JWT-SVID authenticator of the service.
+pub const DEFAULT_SPIFFE_AUDIENCE: &str = "parsec";
+
+/// Authentication method used to talk to the Parsec service.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    /// Direct authentication, with the application name
+    Direct,
+    /// Unix peer credentials authentication
+    UnixPeer,
+    /// JWT-SVID authentication (needs the spiffe-auth feature)
+    JwtSvid,
+    /// No authentication
+    None,
+}
+
+/// Output format of the commands printing information.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// JSON
+    Json,
+}
+
+/// Checks a service endpoint, also accepting the bare path of a Unix domain socket.
+fn parse_endpoint(endpoint: &str) -> std::result::Result<String, String> {
+    if endpoint.starts_with('/') {
+        Ok(format!("unix:{}", endpoint))
+    } else if endpoint
+        .strip_prefix("unix:")
+        .map_or(false, |path| !path.is_empty())
+    {
+        Ok(endpoint.to_string())
+    } else {
+        Err(String::from(
+            "only Unix domain socket endpoints (unix:/path/to/socket) are supported",
+        ))
+    }
+}
+
+/// Checks a SPIFFE Workload API socket, also accepting the bare path of a Unix domain socket.
+fn parse_spiffe_socket(socket: &str) -> std::result::Result<String, String> {
+    if socket.starts_with('/') {
+        Ok(format!("unix:{}", socket))
+    } else if socket.starts_with("unix:") || socket.starts_with("tcp:") {
+        Ok(socket.to_string())
+    } else {
+        Err(String::from(
+            "the Workload API socket must be a unix:/path/to/socket or tcp://IP:port URI",
+        ))
+    }
+}
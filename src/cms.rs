@@ -0,0 +1,203 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encoding of CMS (RFC 5652) SignedData structures, for detached signatures.
+
+use crate::error::{Error, Result, ToolErrorKind};
+use crate::util::Input;
+use parsec_client::core::interface::operations::psa_algorithm::{AsymmetricSignature, Hash};
+use picky_asn1::date::UTCTime;
+use picky_asn1::wrapper::{Asn1SequenceOf, Asn1SetOf, ExplicitContextTag0, OctetStringAsn1};
+use picky_asn1_der::Asn1RawDer;
+use picky_asn1_x509::cmsversion::CmsVersion;
+use picky_asn1_x509::signed_data::{
+    CertificateChoices, CertificateSet, DigestAlgorithmIdentifiers, SignedData, SignersInfos,
+};
+use picky_asn1_x509::signer_info::{
+    Attributes, CertificateSerialNumber, DigestAlgorithmIdentifier, IssuerAndSerialNumber,
+    SignatureAlgorithmIdentifier, SignatureValue, SignerIdentifier, SignerInfo, UnsignedAttributes,
+};
+use picky_asn1_x509::{
+    content_info::EncapsulatedContentInfo, oids, AlgorithmIdentifier, Attribute, Certificate,
+    HashAlgorithm, Pkcs7Certificate, RsassaPssParams, ShaVariant,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the signed attributes of a detached signature of the content: content type,
+/// message digest and signing time.
+///
+/// The attributes are sorted as required by the DER encoding of a SET OF.
+pub fn signed_attributes(
+    content: Input<'_>,
+    hash: Hash,
+    signing_time: SystemTime,
+) -> Result<Vec<Attribute>> {
+    let mut attributes = vec![
+        Attribute::new_content_type_pkcs7(),
+        Attribute::new_message_digest(content.hash(hash)?),
+        Attribute::new_signing_time(utc_time(signing_time)?),
+    ];
+    let mut encoded = Vec::new();
+    for attribute in attributes.drain(..) {
+        encoded.push((encode(&attribute)?, attribute));
+    }
+    encoded.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(encoded
+        .into_iter()
+        .map(|(_, attribute)| attribute)
+        .collect())
+}
+
+/// Returns the data to sign for the given signed attributes, their DER encoding as a SET OF.
+pub fn signed_attributes_data(attributes: &[Attribute]) -> Result<Vec<u8>> {
+    encode(&Asn1SetOf(attributes.to_vec()))
+}
+
+/// Returns the CMS signature algorithm identifier of a PSA signature algorithm.
+pub fn signature_algorithm(
+    key_name: &str,
+    alg: AsymmetricSignature,
+    hash: Hash,
+) -> Result<AlgorithmIdentifier> {
+    match (alg, hash) {
+        (
+            AsymmetricSignature::RsaPkcs1v15Sign { .. } | AsymmetricSignature::RsaPkcs1v15SignRaw,
+            _,
+        ) => Ok(AlgorithmIdentifier::new_rsa_encryption()),
+        (AsymmetricSignature::RsaPss { .. }, _) => Ok(AlgorithmIdentifier::new_rsassa_pss(
+            RsassaPssParams::new(match hash {
+                Hash::Sha224 => HashAlgorithm::SHA224,
+                Hash::Sha256 => HashAlgorithm::SHA256,
+                Hash::Sha384 => HashAlgorithm::SHA384,
+                Hash::Sha512 => HashAlgorithm::SHA512,
+                _ => return Err(unsupported_hash(hash)),
+            }),
+        )),
+        (
+            AsymmetricSignature::Ecdsa { .. }
+            | AsymmetricSignature::DeterministicEcdsa { .. }
+            | AsymmetricSignature::EcdsaAny,
+            Hash::Sha256,
+        ) => Ok(AlgorithmIdentifier::new_ecdsa_with_sha256()),
+        (
+            AsymmetricSignature::Ecdsa { .. }
+            | AsymmetricSignature::DeterministicEcdsa { .. }
+            | AsymmetricSignature::EcdsaAny,
+            Hash::Sha384,
+        ) => Ok(AlgorithmIdentifier::new_ecdsa_with_sha384()),
+        (
+            AsymmetricSignature::Ecdsa { .. }
+            | AsymmetricSignature::DeterministicEcdsa { .. }
+            | AsymmetricSignature::EcdsaAny,
+            Hash::Sha512,
+        ) => Ok(AlgorithmIdentifier::new_ecdsa_with_sha512()),
+        _ => Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+            key_name: key_name.to_string(),
+            algorithm: format!("{:?} with {:?}", alg, hash),
+            operation: "CMS signatures".to_string(),
+        }
+        .into()),
+    }
+}
+
+/// Builds a CMS ContentInfo holding the SignedData of a detached signature made over the signed
+/// attributes, with the signer's certificate.
+pub fn signed_data(
+    certificate_der: &[u8],
+    hash: Hash,
+    signature_algorithm: AlgorithmIdentifier,
+    attributes: Vec<Attribute>,
+    signature: Vec<u8>,
+) -> Result<Vec<u8>> {
+    let certificate: Certificate = picky_asn1_der::from_bytes(certificate_der)
+        .map_err(|_| ToolErrorKind::InvalidData("could not parse the certificate".to_string()))?;
+    let digest_algorithm = AlgorithmIdentifier::new_sha(match hash {
+        Hash::Sha224 => ShaVariant::SHA2_224,
+        Hash::Sha256 => ShaVariant::SHA2_256,
+        Hash::Sha384 => ShaVariant::SHA2_384,
+        Hash::Sha512 => ShaVariant::SHA2_512,
+        _ => return Err(unsupported_hash(hash)),
+    });
+
+    let signer_info = SignerInfo {
+        version: CmsVersion::V1,
+        sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+            issuer: certificate.tbs_certificate.issuer,
+            serial_number: CertificateSerialNumber(certificate.tbs_certificate.serial_number),
+        }),
+        digest_algorithm: DigestAlgorithmIdentifier(digest_algorithm.clone()),
+        signed_attrs: Attributes(Asn1SequenceOf(attributes)).into(),
+        signature_algorithm: SignatureAlgorithmIdentifier(signature_algorithm),
+        signature: SignatureValue(OctetStringAsn1(signature)),
+        unsigned_attrs: UnsignedAttributes::default().into(),
+    };
+
+    let signed_data = SignedData {
+        version: CmsVersion::V1,
+        digest_algorithms: DigestAlgorithmIdentifiers(Asn1SetOf(vec![digest_algorithm])),
+        // The content is detached
+        content_info: EncapsulatedContentInfo::new_pkcs7_data(None),
+        certificates: CertificateSet(vec![CertificateChoices::Certificate(Asn1RawDer(
+            certificate_der.to_vec(),
+        ))])
+        .into(),
+        crls: None,
+        signers_infos: SignersInfos(Asn1SetOf(vec![signer_info])),
+    };
+
+    encode(&Pkcs7Certificate {
+        oid: oids::signed_data().into(),
+        signed_data: ExplicitContextTag0(signed_data),
+    })
+}
+
+fn encode<T: serde::Serialize>(value: &T) -> Result<Vec<u8>> {
+    picky_asn1_der::to_vec(value)
+        .map_err(|_| ToolErrorKind::InvalidData("could not serialise CMS data".to_string()).into())
+}
+
+fn unsupported_hash(hash: Hash) -> Error {
+    ToolErrorKind::UnsupportedOperation(format!(
+        "hash algorithm {:?} is not supported in CMS",
+        hash
+    ))
+    .into()
+}
+
+/// Converts a system time to an ASN.1 UTCTime.
+fn utc_time(time: SystemTime) -> Result<UTCTime> {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| {
+            ToolErrorKind::InvalidData("the system time is before the Unix epoch".to_string())
+        })?
+        .as_secs();
+    let (days, seconds) = (seconds / 86400, seconds % 86400);
+
+    // Civil date from the number of days since the epoch (proleptic Gregorian calendar), see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+
+    UTCTime::new(
+        year as u16,
+        month as u8,
+        day as u8,
+        (seconds / 3600) as u8,
+        (seconds / 60 % 60) as u8,
+        (seconds % 60) as u8,
+    )
+    .ok_or_else(|| {
+        ToolErrorKind::InvalidData(
+            "the signing time can not be represented as a UTCTime".to_string(),
+        )
+        .into()
+    })
+}
@@ -0,0 +1,160 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Configuration file with named profiles.
+//!
+//! The system-wide file (`/etc/parsec-tool.toml`) is read first, then the user's file
+//! (`$XDG_CONFIG_HOME/parsec-tool/config.toml`, `~/.config/parsec-tool/config.toml` by default),
+//! whose settings take precedence. Each profile is a table under `profiles`:
+//!
+//! ```toml
+//! [profiles.default]
+//! endpoint = "unix:/run/parsec/parsec.sock"
+//! provider = 1
+//! timeout = 30
+//! app-name = "my-service"
+//! auth = "unix-peer"
+//! spiffe-socket = "unix:/tmp/spire-agent/public/api.sock"
+//! spiffe-audience = "parsec"
+//! output-format = "json"
+//! ```
+
+use crate::cli::{AuthMethod, OutputFormat};
+use crate::error::{Result, ToolErrorKind};
+use log::debug;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// Path of the system-wide configuration file.
+pub const SYSTEM_CONFIG_PATH: &str = "/etc/parsec-tool.toml";
+
+/// Name of the profile used when none is selected.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Settings of a profile. Unset settings fall back to the defaults of the tool.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    /// Endpoint of the Parsec service
+    pub endpoint: Option<String>,
+    /// ID of the provider to target
+    pub provider: Option<u8>,
+    /// Timeout in seconds, 0 meaning no timeout
+    pub timeout: Option<u32>,
+    /// Application name used by the direct authenticator
+    pub app_name: Option<String>,
+    /// Authenticator to use
+    pub auth: Option<AuthMethod>,
+    /// SPIFFE Workload API socket
+    pub spiffe_socket: Option<String>,
+    /// Audience of the JWT-SVIDs fetched by the tool
+    pub spiffe_audience: Option<String>,
+    /// Output format of the commands printing information
+    pub output_format: Option<OutputFormat>,
+    /// Format of the errors of failed commands
+    pub error_format: Option<OutputFormat>,
+}
+
+impl Profile {
+    /// Overrides the settings of this profile with the ones set in `other`.
+    fn merge(&mut self, other: Profile) {
+        self.endpoint = other.endpoint.or_else(|| self.endpoint.take());
+        self.provider = other.provider.or(self.provider);
+        self.timeout = other.timeout.or(self.timeout);
+        self.app_name = other.app_name.or_else(|| self.app_name.take());
+        self.auth = other.auth.or(self.auth);
+        self.spiffe_socket = other.spiffe_socket.or_else(|| self.spiffe_socket.take());
+        self.spiffe_audience = other
+            .spiffe_audience
+            .or_else(|| self.spiffe_audience.take());
+        self.output_format = other.output_format.or(self.output_format);
+        self.error_format = other.error_format.or(self.error_format);
+    }
+}
+
+/// Content of the configuration files.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Profiles, by name
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    /// Reads the given configuration file, or the system-wide and user files if `None`.
+    ///
+    /// The default files are allowed to be missing, not a file given explicitly.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        if let Some(path) = path {
+            return Config::read(path);
+        }
+
+        let mut config = Config::default();
+        for path in Some(PathBuf::from(SYSTEM_CONFIG_PATH))
+            .into_iter()
+            .chain(user_config_path())
+        {
+            match Config::read(&path) {
+                Ok(file_config) => config.merge(file_config),
+                Err(crate::error::Error::IoError(e)) if e.kind() == ErrorKind::NotFound => {
+                    debug!("No configuration file at {}", path.display());
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Returns the profile with the given name, or the default profile if `None`.
+    ///
+    /// Only a profile selected by name has to exist.
+    pub fn profile(&self, name: Option<&str>) -> Result<Profile> {
+        match name {
+            Some(name) => self.profiles.get(name).cloned().ok_or_else(|| {
+                ToolErrorKind::InvalidData(format!(
+                    "the profile \"{}\" is not defined in the configuration files",
+                    name
+                ))
+                .into()
+            }),
+            None => Ok(self
+                .profiles
+                .get(DEFAULT_PROFILE)
+                .cloned()
+                .unwrap_or_default()),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)?;
+        debug!("Reading configuration file {}", path.display());
+        toml::from_str(&content).map_err(|e| {
+            ToolErrorKind::InvalidData(format!(
+                "could not parse the configuration file {}: {}",
+                path.display(),
+                e
+            ))
+            .into()
+        })
+    }
+
+    fn merge(&mut self, other: Config) {
+        for (name, profile) in other.profiles {
+            self.profiles.entry(name).or_default().merge(profile);
+        }
+    }
+}
+
+/// Path of the user's configuration file.
+fn user_config_path() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("parsec-tool").join("config.toml"))
+}
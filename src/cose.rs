@@ -0,0 +1,372 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encoding of COSE_Sign1 structures (RFC 9052), with the small subset of CBOR (RFC 8949) they
+//! need.
+
+use crate::error::{Result, ToolErrorKind};
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricSignature, Hash, SignHash,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{Attributes, EccFamily, Type};
+
+// CBOR major types
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const BYTES: u8 = 2;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+const TAG: u8 = 6;
+const SIMPLE: u8 = 7;
+
+const CBOR_NULL: u8 = 0xf6;
+const COSE_SIGN1_TAG: u64 = 18;
+
+// Maximum nesting of the CBOR items skipped, to bound the recursion on untrusted input.
+const MAX_NESTING: usize = 16;
+
+// Header parameters
+const HEADER_ALG: i64 = 1;
+const HEADER_KID: i64 = 4;
+
+// Algorithms, from the IANA "COSE Algorithms" registry
+const ES256: i64 = -7;
+const ES384: i64 = -35;
+const PS256: i64 = -37;
+
+/// A COSE_Sign1 structure, with its payload attached.
+#[derive(Debug)]
+pub struct CoseSign1 {
+    /// Serialized protected header
+    pub protected: Vec<u8>,
+    /// Key identifier of the unprotected header
+    pub unprotected_kid: Option<Vec<u8>>,
+    /// Signed content
+    pub payload: Vec<u8>,
+    /// Signature over the Sig_structure
+    pub signature: Vec<u8>,
+}
+
+impl CoseSign1 {
+    /// Serializes a protected header with the algorithm and optionally the key identifier.
+    pub fn protected_header(alg: i64, kid: Option<&[u8]>) -> Vec<u8> {
+        let mut header = Vec::new();
+        put_head(&mut header, MAP, if kid.is_some() { 2 } else { 1 });
+        put_int(&mut header, HEADER_ALG);
+        put_int(&mut header, alg);
+        if let Some(kid) = kid {
+            put_int(&mut header, HEADER_KID);
+            put_bytes(&mut header, kid);
+        }
+        header
+    }
+
+    /// Returns the Sig_structure to be signed, without external additional authenticated data.
+    pub fn to_be_signed(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        put_head(&mut data, ARRAY, 4);
+        put_text(&mut data, "Signature1");
+        put_bytes(&mut data, &self.protected);
+        put_bytes(&mut data, &[]);
+        put_bytes(&mut data, &self.payload);
+        data
+    }
+
+    /// Encodes the tagged COSE_Sign1 structure.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        put_head(&mut data, TAG, COSE_SIGN1_TAG);
+        put_head(&mut data, ARRAY, 4);
+        put_bytes(&mut data, &self.protected);
+        match &self.unprotected_kid {
+            Some(kid) => {
+                put_head(&mut data, MAP, 1);
+                put_int(&mut data, HEADER_KID);
+                put_bytes(&mut data, kid);
+            }
+            None => put_head(&mut data, MAP, 0),
+        }
+        put_bytes(&mut data, &self.payload);
+        put_bytes(&mut data, &self.signature);
+        data
+    }
+
+    /// Decodes a COSE_Sign1 structure, tagged or not. Detached payloads are not supported.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut reader = Reader { data };
+        let (mut major, mut value) = reader.head()?;
+        if major == TAG {
+            if value != COSE_SIGN1_TAG {
+                return Err(ToolErrorKind::InvalidData(format!(
+                    "the COSE structure is not a COSE_Sign1 (tag {})",
+                    value
+                ))
+                .into());
+            }
+            let head = reader.head()?;
+            major = head.0;
+            value = head.1;
+        }
+        if major != ARRAY || value != 4 {
+            return Err(ToolErrorKind::InvalidData(
+                "the COSE_Sign1 structure is not an array of 4 elements".to_string(),
+            )
+            .into());
+        }
+
+        let protected = reader.bytes()?.to_vec();
+        let mut unprotected_kid = None;
+        let (major, entries) = reader.head()?;
+        if major != MAP {
+            return Err(ToolErrorKind::InvalidData(
+                "the COSE unprotected header is not a map".to_string(),
+            )
+            .into());
+        }
+        for _ in 0..entries {
+            if reader.label()? == Some(HEADER_KID) {
+                unprotected_kid = Some(reader.bytes()?.to_vec());
+            } else {
+                reader.skip()?;
+            }
+        }
+        if reader.data.first() == Some(&CBOR_NULL) {
+            return Err(ToolErrorKind::UnsupportedOperation(
+                "detached COSE payloads are not supported".to_string(),
+            )
+            .into());
+        }
+        let payload = reader.bytes()?.to_vec();
+        let signature = reader.bytes()?.to_vec();
+
+        Ok(CoseSign1 {
+            protected,
+            unprotected_kid,
+            payload,
+            signature,
+        })
+    }
+
+    /// Returns the algorithm of the protected header.
+    pub fn algorithm(&self) -> Result<i64> {
+        let mut reader = Reader {
+            data: &self.protected,
+        };
+        let (major, entries) = reader.head()?;
+        if major != MAP {
+            return Err(ToolErrorKind::InvalidData(
+                "the COSE protected header is not a map".to_string(),
+            )
+            .into());
+        }
+        for _ in 0..entries {
+            if reader.label()? == Some(HEADER_ALG) {
+                return reader.int();
+            }
+            reader.skip()?;
+        }
+        Err(
+            ToolErrorKind::InvalidData("the COSE protected header has no algorithm".to_string())
+                .into(),
+        )
+    }
+}
+
+/// Returns the COSE algorithm (ES256, ES384 or PS256) matching the key's policy, with its hash.
+pub fn algorithm(key_name: &str, attributes: &Attributes) -> Result<(i64, Hash)> {
+    // ES256 and ES384 are only defined on the NIST curves.
+    let secp_r1 = matches!(
+        attributes.key_type,
+        Type::EccKeyPair {
+            curve_family: EccFamily::SecpR1
+        } | Type::EccPublicKey {
+            curve_family: EccFamily::SecpR1
+        }
+    );
+    let cose_algorithm = match attributes.policy.permitted_algorithms {
+        Algorithm::AsymmetricSignature(alg) => match (alg, attributes.bits) {
+            (
+                AsymmetricSignature::Ecdsa { hash_alg }
+                | AsymmetricSignature::DeterministicEcdsa { hash_alg },
+                256,
+            ) if secp_r1
+                && (hash_alg == SignHash::Any || hash_alg == SignHash::Specific(Hash::Sha256)) =>
+            {
+                Some((ES256, Hash::Sha256))
+            }
+            (
+                AsymmetricSignature::Ecdsa { hash_alg }
+                | AsymmetricSignature::DeterministicEcdsa { hash_alg },
+                384,
+            ) if secp_r1
+                && (hash_alg == SignHash::Any || hash_alg == SignHash::Specific(Hash::Sha384)) =>
+            {
+                Some((ES384, Hash::Sha384))
+            }
+            (AsymmetricSignature::RsaPss { hash_alg }, _)
+                if hash_alg == SignHash::Any || hash_alg == SignHash::Specific(Hash::Sha256) =>
+            {
+                Some((PS256, Hash::Sha256))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    cose_algorithm.ok_or_else(|| {
+        ToolErrorKind::KeyAlgorithmMismatch {
+            key_name: key_name.to_string(),
+            expected: "an algorithm matching ES256, ES384 or PS256".to_string(),
+            actual: format!(
+                "{:?} ({:?}, {} bits)",
+                attributes.policy.permitted_algorithms, attributes.key_type, attributes.bits
+            ),
+        }
+        .into()
+    })
+}
+
+fn put_head(buf: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    if value < 24 {
+        buf.push(major | value as u8);
+    } else if value <= u8::MAX.into() {
+        buf.push(major | 24);
+        buf.push(value as u8);
+    } else if value <= u16::MAX.into() {
+        buf.push(major | 25);
+        buf.extend_from_slice(&(value as u16).to_be_bytes());
+    } else if value <= u32::MAX.into() {
+        buf.push(major | 26);
+        buf.extend_from_slice(&(value as u32).to_be_bytes());
+    } else {
+        buf.push(major | 27);
+        buf.extend_from_slice(&value.to_be_bytes());
+    }
+}
+
+fn put_int(buf: &mut Vec<u8>, value: i64) {
+    if value < 0 {
+        put_head(buf, NEGATIVE, (-1 - value) as u64);
+    } else {
+        put_head(buf, UNSIGNED, value as u64);
+    }
+}
+
+fn put_bytes(buf: &mut Vec<u8>, data: &[u8]) {
+    put_head(buf, BYTES, data.len() as u64);
+    buf.extend_from_slice(data);
+}
+
+fn put_text(buf: &mut Vec<u8>, text: &str) {
+    put_head(buf, TEXT, text.len() as u64);
+    buf.extend_from_slice(text.as_bytes());
+}
+
+/// Reads definite-length CBOR items from the front of the data.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(
+                ToolErrorKind::InvalidData("the CBOR data is truncated".to_string()).into(),
+            );
+        }
+        let (value, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+
+    /// Reads the major type and argument of the next item.
+    fn head(&mut self) -> Result<(u8, u64)> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let value = match info {
+            0..=23 => info.into(),
+            24 => self.take(1)?[0].into(),
+            25 => self
+                .take(2)?
+                .iter()
+                .fold(0, |acc, b| (acc << 8) | u64::from(*b)),
+            26 => self
+                .take(4)?
+                .iter()
+                .fold(0, |acc, b| (acc << 8) | u64::from(*b)),
+            27 => self
+                .take(8)?
+                .iter()
+                .fold(0, |acc, b| (acc << 8) | u64::from(*b)),
+            _ => {
+                return Err(ToolErrorKind::UnsupportedOperation(
+                    "indefinite-length CBOR items are not supported".to_string(),
+                )
+                .into());
+            }
+        };
+        Ok((major, value))
+    }
+
+    fn int(&mut self) -> Result<i64> {
+        match self.head()? {
+            (UNSIGNED, value) if value <= i64::MAX as u64 => Ok(value as i64),
+            (NEGATIVE, value) if value <= i64::MAX as u64 => Ok(-1 - value as i64),
+            _ => Err(ToolErrorKind::InvalidData("expected a CBOR integer".to_string()).into()),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        match self.head()? {
+            (BYTES, len) => self.take(len as usize),
+            _ => Err(ToolErrorKind::InvalidData("expected a CBOR byte string".to_string()).into()),
+        }
+    }
+
+    /// Reads a header label, returning None for text labels, which the tool does not use.
+    fn label(&mut self) -> Result<Option<i64>> {
+        match self.data.first().map(|initial| initial >> 5) {
+            Some(TEXT) => {
+                self.skip()?;
+                Ok(None)
+            }
+            _ => self.int().map(Some),
+        }
+    }
+
+    /// Skips the next item, with all its content.
+    fn skip(&mut self) -> Result<()> {
+        self.skip_nested(0)
+    }
+
+    fn skip_nested(&mut self, depth: usize) -> Result<()> {
+        if depth > MAX_NESTING {
+            return Err(ToolErrorKind::UnsupportedOperation(format!(
+                "CBOR items nested more than {} levels deep are not supported",
+                MAX_NESTING
+            ))
+            .into());
+        }
+        match self.head()? {
+            (UNSIGNED, _) | (NEGATIVE, _) | (SIMPLE, _) => {}
+            (BYTES, len) | (TEXT, len) => {
+                let _ = self.take(len as usize)?;
+            }
+            (ARRAY, len) => {
+                for _ in 0..len {
+                    self.skip_nested(depth + 1)?;
+                }
+            }
+            (MAP, len) => {
+                for _ in 0..len.saturating_mul(2) {
+                    self.skip_nested(depth + 1)?;
+                }
+            }
+            (TAG, _) => self.skip_nested(depth + 1)?,
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+}
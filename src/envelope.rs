@@ -0,0 +1,278 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Container of envelope (hybrid) encryption.
+//!
+//! The payload is encrypted locally with AES-256-GCM under a random content key, and the content
+//! key is wrapped with a Parsec key. The container is laid out as follows, with integers in
+//! big-endian:
+//!
+//! | Field        | Size                                          |
+//! |--------------|-----------------------------------------------|
+//! | magic        | 7 bytes, `PSECENV`                            |
+//! | version      | 1 byte, 1                                     |
+//! | key wrapping | 1 byte, 1 for RSA-OAEP and 2 for ECIES         |
+//! | wrapped key  | 4 bytes length followed by the wrapped key    |
+//! | nonce        | 12 bytes                                      |
+//! | ciphertext   | rest of the container, with a 16 bytes tag    |
+//!
+//! All the fields before the ciphertext are authenticated as additional data.
+//!
+//! With ECIES, the wrapped key is the ephemeral public key (an uncompressed point) of an ECDH
+//! exchange with the Parsec key. The content key is derived from the shared secret with
+//! HKDF-SHA256, without salt, the info being the ephemeral public key followed by the public key
+//! of the Parsec key.
+
+use crate::error::{Result, ToolErrorKind};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::agreement::{self, EphemeralPrivateKey, UnparsedPublicKey};
+use ring::hkdf::{KeyType, Salt, HKDF_SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::convert::TryInto;
+use zeroize::Zeroizing;
+
+const MAGIC: &[u8] = b"PSECENV";
+const VERSION: u8 = 1;
+
+/// Length of the AES-256-GCM content key.
+pub const CONTENT_KEY_LEN: usize = 32;
+/// Length of the AES-256-GCM nonce.
+pub const CONTENT_NONCE_LEN: usize = NONCE_LEN;
+
+/// How the content key is wrapped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyWrapping {
+    /// Encrypted with the RSA-OAEP policy of a Parsec key
+    RsaOaep,
+    /// Derived from an ECDH exchange with a Parsec key
+    Ecies,
+}
+
+impl KeyWrapping {
+    fn id(self) -> u8 {
+        match self {
+            KeyWrapping::RsaOaep => 1,
+            KeyWrapping::Ecies => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(KeyWrapping::RsaOaep),
+            2 => Ok(KeyWrapping::Ecies),
+            other => Err(ToolErrorKind::UnsupportedOperation(format!(
+                "unknown key wrapping ({}) in the envelope",
+                other
+            ))
+            .into()),
+        }
+    }
+}
+
+/// An encrypted payload with its wrapped content key.
+#[derive(Debug)]
+pub struct Envelope {
+    /// How the content key is wrapped
+    pub key_wrapping: KeyWrapping,
+    /// Wrapped content key
+    pub wrapped_key: Vec<u8>,
+    /// Nonce of the payload encryption
+    pub nonce: [u8; CONTENT_NONCE_LEN],
+    /// Encrypted payload, with the authentication tag
+    pub ciphertext: Vec<u8>,
+}
+
+impl Envelope {
+    /// Returns true if the data starts like an envelope.
+    pub fn is_envelope(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Encrypts the payload with the content key, which was wrapped in `wrapped_key`.
+    pub fn seal(
+        key_wrapping: KeyWrapping,
+        wrapped_key: Vec<u8>,
+        content_key: &[u8],
+        nonce: &[u8],
+        payload: &[u8],
+    ) -> Result<Self> {
+        let mut envelope = Envelope {
+            key_wrapping,
+            wrapped_key,
+            nonce: nonce.try_into().map_err(|_| {
+                ToolErrorKind::InvalidData(format!(
+                    "the nonce must be {} bytes long",
+                    CONTENT_NONCE_LEN
+                ))
+            })?,
+            ciphertext: payload.to_vec(),
+        };
+        let header = envelope.header();
+        content_cipher(content_key)?
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(envelope.nonce),
+                Aad::from(header),
+                &mut envelope.ciphertext,
+            )
+            .map_err(|_| ToolErrorKind::InvalidData("could not encrypt the payload".to_string()))?;
+        Ok(envelope)
+    }
+
+    /// Decrypts the payload with the unwrapped content key.
+    pub fn open(&self, content_key: &[u8]) -> Result<Vec<u8>> {
+        let mut payload = self.ciphertext.clone();
+        let len = content_cipher(content_key)?
+            .open_in_place(
+                Nonce::assume_unique_for_key(self.nonce),
+                Aad::from(self.header()),
+                &mut payload,
+            )
+            .map_err(|_| {
+                ToolErrorKind::InvalidData(
+                    "could not decrypt the payload: the envelope was modified or the key is wrong"
+                        .to_string(),
+                )
+            })?
+            .len();
+        payload.truncate(len);
+        Ok(payload)
+    }
+
+    /// Encodes the container.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = self.header();
+        data.extend_from_slice(&self.ciphertext);
+        data
+    }
+
+    /// Decodes a container.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let mut data = match data.strip_prefix(MAGIC) {
+            Some(data) => data,
+            None => {
+                return Err(
+                    ToolErrorKind::InvalidData("the data is not an envelope".to_string()).into(),
+                );
+            }
+        };
+        let version = take(&mut data, 1)?[0];
+        if version != VERSION {
+            return Err(ToolErrorKind::UnsupportedOperation(format!(
+                "envelope version {} is not supported",
+                version
+            ))
+            .into());
+        }
+        let key_wrapping = KeyWrapping::from_id(take(&mut data, 1)?[0])?;
+        let wrapped_key_len = u32::from_be_bytes(take(&mut data, 4)?.try_into().unwrap());
+        let wrapped_key = take(&mut data, wrapped_key_len as usize)?.to_vec();
+        let nonce = take(&mut data, CONTENT_NONCE_LEN)?.try_into().unwrap();
+
+        Ok(Envelope {
+            key_wrapping,
+            wrapped_key,
+            nonce,
+            ciphertext: data.to_vec(),
+        })
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = MAGIC.to_vec();
+        header.push(VERSION);
+        header.push(self.key_wrapping.id());
+        header.extend_from_slice(&(self.wrapped_key.len() as u32).to_be_bytes());
+        header.extend_from_slice(&self.wrapped_key);
+        header.extend_from_slice(&self.nonce);
+        header
+    }
+}
+
+/// Generates the nonce of an envelope locally, for envelopes made without the Parsec service.
+pub fn local_nonce() -> Result<Vec<u8>> {
+    let mut nonce = vec![0; CONTENT_NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| ToolErrorKind::InvalidData("could not generate the nonce".to_string()))?;
+    Ok(nonce)
+}
+
+/// Makes the ECDH exchange of ECIES encryption, for a NIST curve public key of the given size.
+///
+/// Returns the ephemeral public key, to be stored in the envelope, and the content key.
+pub fn ecies_encapsulate(public_key: &[u8], bits: usize) -> Result<(Vec<u8>, Zeroizing<Vec<u8>>)> {
+    let algorithm = match bits {
+        256 => &agreement::ECDH_P256,
+        384 => &agreement::ECDH_P384,
+        other => {
+            return Err(ToolErrorKind::UnsupportedOperation(format!(
+                "ECIES is only supported on P-256 and P-384, not on a {} bits curve",
+                other
+            ))
+            .into());
+        }
+    };
+    let ephemeral_key =
+        EphemeralPrivateKey::generate(algorithm, &SystemRandom::new()).map_err(|_| {
+            ToolErrorKind::InvalidData("could not generate the ephemeral key".to_string())
+        })?;
+    let ephemeral_public_key = ephemeral_key
+        .compute_public_key()
+        .map_err(|_| {
+            ToolErrorKind::InvalidData("could not compute the ephemeral public key".to_string())
+        })?
+        .as_ref()
+        .to_vec();
+
+    let content_key = agreement::agree_ephemeral(
+        ephemeral_key,
+        &UnparsedPublicKey::new(algorithm, public_key),
+        |shared_secret| ecies_content_key(shared_secret, &ephemeral_public_key, public_key),
+    )
+    .map_err(|_| {
+        ToolErrorKind::InvalidData("the public key is not a valid point of the curve".to_string())
+    })??;
+
+    Ok((ephemeral_public_key, content_key))
+}
+
+/// Derives the content key of ECIES encryption from the ECDH shared secret.
+pub fn ecies_content_key(
+    shared_secret: &[u8],
+    ephemeral_public_key: &[u8],
+    public_key: &[u8],
+) -> Result<Zeroizing<Vec<u8>>> {
+    let mut content_key = Zeroizing::new(vec![0; CONTENT_KEY_LEN]);
+    Salt::new(HKDF_SHA256, &[])
+        .extract(shared_secret)
+        .expand(&[ephemeral_public_key, public_key], ContentKeyLen)
+        .and_then(|okm| okm.fill(&mut content_key))
+        .map_err(|_| ToolErrorKind::InvalidData("could not derive the content key".to_string()))?;
+    Ok(content_key)
+}
+
+struct ContentKeyLen;
+
+impl KeyType for ContentKeyLen {
+    fn len(&self) -> usize {
+        CONTENT_KEY_LEN
+    }
+}
+
+fn content_cipher(content_key: &[u8]) -> Result<LessSafeKey> {
+    let key = UnboundKey::new(&AES_256_GCM, content_key).map_err(|_| {
+        ToolErrorKind::InvalidData(format!(
+            "the content key must be {} bytes long",
+            CONTENT_KEY_LEN
+        ))
+    })?;
+    Ok(LessSafeKey::new(key))
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        return Err(ToolErrorKind::InvalidData("the envelope is truncated".to_string()).into());
+    }
+    let (value, rest) = data.split_at(len);
+    *data = rest;
+    Ok(value)
+}
@@ -3,6 +3,8 @@
 
 //! Error definitions/handling.
 
+use parsec_client::core::interface::requests::ResponseStatus;
+use parsec_client::error::{ClientErrorKind, Error as ClientError};
 use thiserror::Error;
 
 /// Errors in parsec-tool.
@@ -14,11 +16,11 @@ pub enum Error {
 
     /// Error emanating from the parsec_client crate.
     #[error(transparent)]
-    ParsecClientError(#[from] parsec_client::error::Error),
+    ParsecClientError(#[from] ClientError),
 
     /// Error emanating from the parsec_client::core::interface crate.
     #[error(transparent)]
-    ParsecInterfaceError(#[from] parsec_client::core::interface::requests::ResponseStatus),
+    ParsecInterfaceError(#[from] ResponseStatus),
 
     /// Error emanating from the parsec-tool.
     #[error(transparent)]
@@ -36,21 +38,204 @@ pub enum Error {
 /// Errors originating in the parsec-tool.
 #[derive(Error, Debug)]
 pub enum ToolErrorKind {
-    /// Operation not supported by the parsec-tool
-    #[error("Operation not supported by the parsec-tool")]
-    NotSupported,
+    /// The algorithm of the key cannot be used for the operation by the parsec-tool
+    #[error("The algorithm {algorithm} of key \"{key_name}\" is not supported for {operation}")]
+    UnsupportedKeyAlgorithm {
+        /// Name of the key
+        key_name: String,
+        /// Algorithm, or type, of the key
+        algorithm: String,
+        /// Operation attempted with the key
+        operation: String,
+    },
 
-    /// They key was not created with the correct algorithm for this operation
-    #[error("They key was not created with the correct algorithm for this operation")]
-    WrongKeyAlgorithm,
+    /// The key was not created with the algorithm needed by the operation
+    #[error("Key \"{key_name}\" has {actual}, but {expected} is needed")]
+    KeyAlgorithmMismatch {
+        /// Name of the key
+        key_name: String,
+        /// What the operation needs
+        expected: String,
+        /// What the key has
+        actual: String,
+    },
 
-    /// Expected input data was not given
-    #[error("A command expected input data that was not given")]
-    NoInput,
+    /// Operation not supported by the parsec-tool, for the given reason
+    #[error("Operation not supported by the parsec-tool: {0}")]
+    UnsupportedOperation(String),
 
-    /// Cannot serialise or deserialise data
-    #[error("Incorrect data format")]
-    IncorrectData,
+    /// Expected input data was not given, the missing input is described
+    #[error("A command expected input data that was not given: {0}")]
+    MissingInput(String),
+
+    /// Cannot serialise, deserialise or use data, for the given reason
+    #[error("Incorrect data format: {0}")]
+    InvalidData(String),
+
+    /// A signature checked by the parsec-tool is not valid, for the given reason
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+
+    /// The authenticator asked for is not enabled on the Parsec service
+    #[error(
+        "{requested} is not enabled on the Parsec service (enabled authenticators: {enabled})"
+    )]
+    AuthenticatorNotEnabled {
+        /// Authenticator asked for
+        requested: String,
+        /// Authenticators enabled on the service, or "none"
+        enabled: String,
+    },
+
+    /// No JWT-SVID could be fetched from the SPIFFE Workload API
+    #[error("Could not fetch a JWT-SVID with the \"{audience}\" audience from the Workload API: {reason}")]
+    JwtSvidUnavailable {
+        /// Audience of the JWT-SVID
+        audience: String,
+        /// Error of the Workload API client
+        reason: String,
+    },
+}
+
+impl Error {
+    /// Category of the error, giving the exit code of the tool.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::IoError(_) => ErrorCategory::Io,
+            Error::ParsecClientError(ClientError::Service(status)) => status_category(*status),
+            Error::ParsecClientError(ClientError::Client(kind)) => match kind {
+                ClientErrorKind::Interface(status) => status_category(*status),
+                ClientErrorKind::Ipc(_)
+                | ClientErrorKind::InvalidSocketAddress
+                | ClientErrorKind::InvalidSocketUrl => ErrorCategory::Connection,
+                ClientErrorKind::NoAuthenticator => ErrorCategory::PermissionDenied,
+                ClientErrorKind::NoProvider | ClientErrorKind::InvalidProvider => {
+                    ErrorCategory::NotSupported
+                }
+                ClientErrorKind::NotFound => ErrorCategory::KeyNotFound,
+                _ => ErrorCategory::Generic,
+            },
+            Error::ParsecInterfaceError(status) => status_category(*status),
+            Error::ParsecToolError(ToolErrorKind::UnsupportedKeyAlgorithm { .. })
+            | Error::ParsecToolError(ToolErrorKind::UnsupportedOperation(_)) => {
+                ErrorCategory::NotSupported
+            }
+            Error::ParsecToolError(ToolErrorKind::InvalidSignature(_)) => {
+                ErrorCategory::InvalidSignature
+            }
+            Error::ParsecToolError(ToolErrorKind::AuthenticatorNotEnabled { .. })
+            | Error::ParsecToolError(ToolErrorKind::JwtSvidUnavailable { .. }) => {
+                ErrorCategory::PermissionDenied
+            }
+            Error::ParsecToolError(_) | Error::Base64Decode(_) => ErrorCategory::InvalidInput,
+            Error::RcgenError(error) => match error {
+                rcgen::Error::InvalidAsn1String(_)
+                | rcgen::Error::InvalidIpAddressOctetLength(_)
+                | rcgen::Error::UnsupportedInCsr => ErrorCategory::InvalidInput,
+                rcgen::Error::UnsupportedSignatureAlgorithm
+                | rcgen::Error::KeyGenerationUnavailable => ErrorCategory::NotSupported,
+                _ => ErrorCategory::Generic,
+            },
+        }
+    }
+
+    /// Response status of the Parsec service at the origin of the error, if any.
+    pub fn response_status(&self) -> Option<ResponseStatus> {
+        match self {
+            Error::ParsecClientError(ClientError::Service(status))
+            | Error::ParsecClientError(ClientError::Client(ClientErrorKind::Interface(status)))
+            | Error::ParsecInterfaceError(status) => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Name of the response status at the origin of the error, or else the error message.
+    pub fn status_or_message(&self) -> String {
+        self.response_status()
+            .map_or_else(|| self.to_string(), |status| format!("{:?}", status))
+    }
+}
+
+fn status_category(status: ResponseStatus) -> ErrorCategory {
+    match status {
+        ResponseStatus::ConnectionError => ErrorCategory::Connection,
+        ResponseStatus::AuthenticationError
+        | ResponseStatus::AuthenticatorDoesNotExist
+        | ResponseStatus::AuthenticatorNotRegistered
+        | ResponseStatus::NotAuthenticated
+        | ResponseStatus::AdminOperation
+        | ResponseStatus::PsaErrorNotPermitted => ErrorCategory::PermissionDenied,
+        ResponseStatus::PsaErrorDoesNotExist => ErrorCategory::KeyNotFound,
+        ResponseStatus::PsaErrorAlreadyExists => ErrorCategory::KeyAlreadyExists,
+        ResponseStatus::WrongProviderId
+        | ResponseStatus::WrongProviderUuid
+        | ResponseStatus::ContentTypeNotSupported
+        | ResponseStatus::AcceptTypeNotSupported
+        | ResponseStatus::WireProtocolVersionNotSupported
+        | ResponseStatus::ProviderNotRegistered
+        | ResponseStatus::ProviderDoesNotExist
+        | ResponseStatus::OpcodeDoesNotExist
+        | ResponseStatus::DeprecatedPrimitive
+        | ResponseStatus::PsaErrorNotSupported => ErrorCategory::NotSupported,
+        ResponseStatus::PsaErrorInvalidArgument
+        | ResponseStatus::PsaErrorInvalidPadding
+        | ResponseStatus::PsaErrorBufferTooSmall
+        | ResponseStatus::PsaErrorInsufficientData
+        | ResponseStatus::BodySizeExceedsLimit => ErrorCategory::InvalidInput,
+        ResponseStatus::PsaErrorInvalidSignature => ErrorCategory::InvalidSignature,
+        _ => ErrorCategory::Service,
+    }
+}
+
+/// Categories of errors, each with its own exit code.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Any other error
+    Generic = 1,
+    /// Wrong command-line arguments, reported by the argument parser
+    Usage = 2,
+    /// The Parsec service could not be reached
+    Connection = 3,
+    /// The authentication failed or the operation is not permitted
+    PermissionDenied = 4,
+    /// The key does not exist
+    KeyNotFound = 5,
+    /// A key with the same name already exists
+    KeyAlreadyExists = 6,
+    /// The operation or algorithm is not supported by the tool, the service or the provider
+    NotSupported = 7,
+    /// The input data or the arguments are invalid
+    InvalidInput = 8,
+    /// The signature is not valid
+    InvalidSignature = 9,
+    /// A local file could not be read or written
+    Io = 10,
+    /// The service failed to perform the operation
+    Service = 11,
+}
+
+impl ErrorCategory {
+    /// Exit code of the tool for this category.
+    pub fn exit_code(self) -> i32 {
+        self as i32
+    }
+
+    /// Name of the category, as written in structured errors.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::Generic => "generic",
+            ErrorCategory::Usage => "usage",
+            ErrorCategory::Connection => "connection",
+            ErrorCategory::PermissionDenied => "permission-denied",
+            ErrorCategory::KeyNotFound => "key-not-found",
+            ErrorCategory::KeyAlreadyExists => "key-already-exists",
+            ErrorCategory::NotSupported => "not-supported",
+            ErrorCategory::InvalidInput => "invalid-input",
+            ErrorCategory::InvalidSignature => "invalid-signature",
+            ErrorCategory::Io => "io",
+            ErrorCategory::Service => "service",
+        }
+    }
 }
 
 /// A Result type with the Err variant set as a ParsecToolError
@@ -35,7 +35,12 @@
 #![allow(clippy::multiple_crate_versions)]
 
 pub mod cli;
+pub mod cms;
 pub mod common;
+pub mod config;
+pub mod cose;
+pub mod envelope;
 pub mod error;
+pub mod ssh;
 pub mod subcommands;
 pub mod util;
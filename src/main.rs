@@ -5,10 +5,34 @@
 
 use clap::Parser;
 use log::error;
-use parsec_tool::cli;
+use parsec_tool::cli::{self, OutputFormat, DEFAULT_SPIFFE_AUDIENCE};
 use parsec_tool::common::PROJECT_NAME;
+use parsec_tool::error::Error;
+use serde_json::json;
 use std::convert::TryInto;
 
+/// Reports the error in the given format and exits with the code of its category.
+fn exit_with_error(context: &str, error: Error, format: OutputFormat) -> ! {
+    let category = error.category();
+    match format {
+        OutputFormat::Text => error!("{}: {} ({:?})", context, error, error),
+        OutputFormat::Json => {
+            let mut object = json!({
+                "context": context,
+                "error": error.to_string(),
+                "category": category.name(),
+                "exit_code": category.exit_code(),
+            });
+            if let Some(status) = error.response_status() {
+                object["status"] = json!(format!("{:?}", status));
+                object["status_code"] = json!(status as u16);
+            }
+            eprintln!("{}", object);
+        }
+    }
+    std::process::exit(category.exit_code());
+}
+
 fn main() {
     let mut env_log_builder = env_logger::Builder::new();
     // By default, only show the logs from this crate.
@@ -20,27 +44,48 @@ fn main() {
     env_log_builder.parse_default_env();
     env_log_builder.init();
 
-    let matches = cli::ParsecToolApp::parse();
+    let mut matches = cli::ParsecToolApp::parse();
+    if let Err(e) = matches.apply_config() {
+        let error_format = matches.error_format.unwrap_or_default();
+        exit_with_error("Error reading the configuration", e, error_format);
+    }
+    let error_format = matches.error_format.unwrap_or_default();
 
-    let mut client = match matches
-        .subcommand
-        .create_client(Some(PROJECT_NAME.to_string()))
-    {
-        Err(e) => {
-            error!("Error spinning up the BasicClient: {}", e);
-            std::process::exit(1);
-        }
-        Ok(client) => client,
-    };
-
-    if let Some(provider) = matches.provider {
-        let provider = match provider.try_into() {
-            Err(_) => {
-                error!("The provider ID entered does not map with an existing provider");
-                std::process::exit(1);
-            }
-            Ok(provider) => provider,
+    // The client reads the endpoint from the environment when it is created.
+    if let Some(endpoint) = &matches.endpoint {
+        std::env::set_var("PARSEC_SERVICE_ENDPOINT", endpoint);
+    }
+    // Same for the SPIFFE Workload API socket.
+    if let Some(socket) = &matches.spiffe_socket {
+        std::env::set_var("SPIFFE_ENDPOINT_SOCKET", socket);
+    }
+
+    let app_name = matches
+        .app_name
+        .clone()
+        .unwrap_or_else(|| PROJECT_NAME.to_string());
+    let spiffe_audience = matches
+        .spiffe_audience
+        .as_deref()
+        .unwrap_or(DEFAULT_SPIFFE_AUDIENCE);
+    let mut client =
+        match matches
+            .subcommand
+            .create_client(Some(app_name), matches.auth, spiffe_audience)
+        {
+            Err(e) => exit_with_error("Error spinning up the BasicClient", e, error_format),
+            Ok(client) => client,
         };
+
+    let provider = matches.provider.map(|provider| match provider.try_into() {
+        Err(e) => exit_with_error(
+            "The provider ID entered does not map with an existing provider",
+            Error::ParsecInterfaceError(e),
+            error_format,
+        ),
+        Ok(provider) => provider,
+    });
+    if let Some(provider) = provider {
         client.set_implicit_provider(provider);
     }
 
@@ -53,9 +98,12 @@ fn main() {
         client.set_timeout(timeout);
     }
 
-    if let Err(e) = matches.subcommand.run(client) {
-        error!("Subcommand failed: {} ({:?})", e, e);
-        std::process::exit(1);
+    let output_format = matches.output_format.unwrap_or_default();
+    if let Err(e) = matches
+        .subcommand
+        .run(client, output_format, provider, spiffe_audience)
+    {
+        exit_with_error("Subcommand failed", e, error_format);
     }
 
     std::process::exit(0);
@@ -0,0 +1,334 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Encoding helpers for the SSH wire format (RFC 4251) and OpenSSH public keys.
+
+use crate::error::{Result, ToolErrorKind};
+use crate::util::{EccSignature, Input};
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricSignature, Hash, SignHash,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{Attributes, EccFamily, Type};
+use picky_asn1::wrapper::IntegerAsn1;
+use picky_asn1_x509::RsaPublicKey;
+
+const SSHSIG_MAGIC: &[u8] = b"SSHSIG";
+const SSHSIG_VERSION: u32 = 1;
+const SSHSIG_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+const SSHSIG_END: &str = "-----END SSH SIGNATURE-----";
+
+/// Appends an SSH `string` (a length-prefixed byte array) to the buffer.
+pub fn put_string(buf: &mut Vec<u8>, data: &[u8]) {
+    buf.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Appends an SSH `mpint` to the buffer, from an unsigned big-endian integer.
+pub fn put_mpint(buf: &mut Vec<u8>, data: &[u8]) {
+    let first_non_zero = data.iter().position(|byte| *byte != 0);
+    let data = match first_non_zero {
+        Some(index) => &data[index..],
+        None => &[],
+    };
+    if data.first().map_or(false, |byte| byte & 0x80 != 0) {
+        // Prepend a zero byte so that the number is not read as negative.
+        buf.extend_from_slice(&(data.len() as u32 + 1).to_be_bytes());
+        buf.push(0);
+        buf.extend_from_slice(data);
+    } else {
+        put_string(buf, data);
+    }
+}
+
+/// Reads an SSH `uint32` from the front of the buffer, advancing the buffer past it.
+pub fn get_u32(buf: &mut &[u8]) -> Result<u32> {
+    if buf.len() < 4 {
+        return Err(ToolErrorKind::InvalidData("SSH message is truncated".to_string()).into());
+    }
+    let (value, rest) = buf.split_at(4);
+    *buf = rest;
+    Ok(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+/// Reads an SSH `string` from the front of the buffer, advancing the buffer past it.
+pub fn get_string<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = get_u32(buf)? as usize;
+    if buf.len() < len {
+        return Err(ToolErrorKind::InvalidData("SSH message is truncated".to_string()).into());
+    }
+    let (value, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(value)
+}
+
+/// Builds the SSH signature blob of an ECDSA signature, from the raw `r || s` signature produced
+/// by Parsec.
+pub fn ecdsa_signature_blob(key_type: &str, raw_signature: &[u8]) -> Vec<u8> {
+    let (r, s) = raw_signature.split_at(raw_signature.len() / 2);
+    let mut rs = Vec::new();
+    put_mpint(&mut rs, r);
+    put_mpint(&mut rs, s);
+
+    let mut blob = Vec::new();
+    put_string(&mut blob, key_type.as_bytes());
+    put_string(&mut blob, &rs);
+    blob
+}
+
+/// Converts an ECDSA signature blob to the DER encoding of the signature.
+pub fn ecdsa_signature_to_der(signature: &[u8]) -> Result<Vec<u8>> {
+    let mut signature = signature;
+    let r = get_string(&mut signature)?;
+    let s = get_string(&mut signature)?;
+    picky_asn1_der::to_vec(&EccSignature {
+        r: IntegerAsn1::from_bytes_be_signed(r.to_vec()),
+        s: IntegerAsn1::from_bytes_be_signed(s.to_vec()),
+    })
+    .map_err(|_| {
+        ToolErrorKind::InvalidData("could not serialise the ECDSA signature".to_string()).into()
+    })
+}
+
+/// Returns the hash used by SSH for ECDSA signatures with a NIST curve of the given size.
+pub fn ecdsa_hash(bits: usize) -> Option<Hash> {
+    match bits {
+        256 => Some(Hash::Sha256),
+        384 => Some(Hash::Sha384),
+        521 => Some(Hash::Sha512),
+        _ => None,
+    }
+}
+
+/// Returns the hash to sign SSH data with a key, following its policy, and the SSH signature type
+/// that comes with it. RSA keys allowing any hash sign with SHA-512, like `ssh-keygen`.
+pub fn signature_scheme(key_name: &str, attributes: &Attributes) -> Result<(Hash, String)> {
+    let policy = match attributes.policy.permitted_algorithms {
+        Algorithm::AsymmetricSignature(policy) => policy,
+        other => {
+            return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                key_name: key_name.to_string(),
+                expected: "an asymmetric signature algorithm".to_string(),
+                actual: format!("{:?}", other),
+            }
+            .into());
+        }
+    };
+    let unsupported = |algorithm: String| ToolErrorKind::UnsupportedKeyAlgorithm {
+        key_name: key_name.to_string(),
+        algorithm,
+        operation: "SSH signatures".to_string(),
+    };
+    match (attributes.key_type, policy) {
+        (Type::RsaKeyPair, AsymmetricSignature::RsaPkcs1v15Sign { hash_alg }) => match hash_alg {
+            SignHash::Any | SignHash::Specific(Hash::Sha512) => {
+                Ok((Hash::Sha512, String::from("rsa-sha2-512")))
+            }
+            SignHash::Specific(Hash::Sha256) => Ok((Hash::Sha256, String::from("rsa-sha2-256"))),
+            SignHash::Specific(_) => Err(unsupported(format!("{:?}", policy)).into()),
+        },
+        (
+            Type::EccKeyPair { curve_family },
+            AsymmetricSignature::Ecdsa { hash_alg }
+            | AsymmetricSignature::DeterministicEcdsa { hash_alg },
+        ) => {
+            let key_type = format!(
+                "ecdsa-sha2-{}",
+                ecdsa_curve_name(key_name, curve_family, attributes.bits)?
+            );
+            // ecdsa_curve_name only accepts NIST curves, which all have a hash.
+            let hash = ecdsa_hash(attributes.bits)
+                .ok_or_else(|| unsupported(format!("{} bits ECDSA", attributes.bits)))?;
+            if hash_alg == SignHash::Any || hash_alg == SignHash::Specific(hash) {
+                Ok((hash, key_type))
+            } else {
+                // SSH signatures with this curve must use this hash.
+                Err(ToolErrorKind::KeyAlgorithmMismatch {
+                    key_name: key_name.to_string(),
+                    expected: format!("{:?}", hash),
+                    actual: format!("{:?}", hash_alg),
+                }
+                .into())
+            }
+        }
+        _ => Err(unsupported(format!("{:?}", policy)).into()),
+    }
+}
+
+/// Returns the SSH curve identifier of a NIST curve of the given size.
+pub fn ecdsa_curve_name(key_name: &str, curve: EccFamily, bits: usize) -> Result<&'static str> {
+    match (curve, bits) {
+        (EccFamily::SecpR1, 256) => Ok("nistp256"),
+        (EccFamily::SecpR1, 384) => Ok("nistp384"),
+        (EccFamily::SecpR1, 521) => Ok("nistp521"),
+        // SSH only supports the NIST P-256, P-384 and P-521 curves.
+        _ => Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+            key_name: key_name.to_string(),
+            algorithm: format!("{} bits keys of Ecc family \"{}\"", bits, curve),
+            operation: "SSH".to_string(),
+        }
+        .into()),
+    }
+}
+
+/// Builds the SSH public key blob of a key, from its attributes and its public part exported by
+/// Parsec. Returns the SSH key type name along with the blob.
+pub fn public_key_blob(
+    key_name: &str,
+    attributes: &Attributes,
+    psa_public_key: &[u8],
+) -> Result<(String, Vec<u8>)> {
+    let mut blob = Vec::new();
+    match attributes.key_type {
+        Type::RsaKeyPair | Type::RsaPublicKey => {
+            let rsa_public_key = picky_asn1_der::from_bytes::<RsaPublicKey>(psa_public_key)
+                .map_err(|_| {
+                    ToolErrorKind::InvalidData(format!(
+                        "could not deserialise the RSA key \"{}\"",
+                        key_name
+                    ))
+                })?;
+            let key_type = String::from("ssh-rsa");
+            put_string(&mut blob, key_type.as_bytes());
+            put_mpint(
+                &mut blob,
+                rsa_public_key.public_exponent.as_unsigned_bytes_be(),
+            );
+            put_mpint(&mut blob, rsa_public_key.modulus.as_unsigned_bytes_be());
+            Ok((key_type, blob))
+        }
+        Type::EccKeyPair {
+            curve_family: curve,
+        }
+        | Type::EccPublicKey {
+            curve_family: curve,
+        } => {
+            let curve_name = ecdsa_curve_name(key_name, curve, attributes.bits)?;
+            let key_type = format!("ecdsa-sha2-{}", curve_name);
+            put_string(&mut blob, key_type.as_bytes());
+            put_string(&mut blob, curve_name.as_bytes());
+            put_string(&mut blob, psa_public_key);
+            Ok((key_type, blob))
+        }
+        other => Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+            key_name: key_name.to_string(),
+            algorithm: format!("{:?}", other),
+            operation: "SSH".to_string(),
+        }
+        .into()),
+    }
+}
+
+/// An SSH signature in the SSHSIG format of OpenSSH (see `PROTOCOL.sshsig`), as produced by
+/// `ssh-keygen -Y sign`.
+#[derive(Debug)]
+pub struct SshSignature {
+    /// SSH public key blob of the signing key
+    pub public_key: Vec<u8>,
+    /// Domain of the signature, for example "git" or "file"
+    pub namespace: String,
+    /// Name of the algorithm used to hash the message
+    pub hash_algorithm: String,
+    /// SSH signature blob
+    pub signature: Vec<u8>,
+}
+
+impl SshSignature {
+    /// Returns the data to be signed for a message in the given namespace.
+    pub fn signed_data(
+        namespace: &str,
+        hash_algorithm: &str,
+        message: Input<'_>,
+    ) -> Result<Vec<u8>> {
+        let hash = match hash_algorithm {
+            "sha256" => Hash::Sha256,
+            "sha512" => Hash::Sha512,
+            other => {
+                return Err(ToolErrorKind::UnsupportedOperation(format!(
+                    "SSH signature hash algorithm \"{}\" is not supported",
+                    other
+                ))
+                .into());
+            }
+        };
+
+        let mut data = SSHSIG_MAGIC.to_vec();
+        put_string(&mut data, namespace.as_bytes());
+        // Reserved
+        put_string(&mut data, &[]);
+        put_string(&mut data, hash_algorithm.as_bytes());
+        put_string(&mut data, &message.hash(hash)?);
+        Ok(data)
+    }
+
+    /// Encodes the signature in the armored format of `ssh-keygen`.
+    pub fn to_armored(&self) -> String {
+        let mut blob = SSHSIG_MAGIC.to_vec();
+        blob.extend_from_slice(&SSHSIG_VERSION.to_be_bytes());
+        put_string(&mut blob, &self.public_key);
+        put_string(&mut blob, self.namespace.as_bytes());
+        put_string(&mut blob, &[]);
+        put_string(&mut blob, self.hash_algorithm.as_bytes());
+        put_string(&mut blob, &self.signature);
+
+        let encoded = base64::encode(blob);
+        let mut armored = String::from(SSHSIG_BEGIN);
+        armored.push('\n');
+        // Same line length as ssh-keygen
+        for line in encoded.as_bytes().chunks(70) {
+            armored.push_str(&String::from_utf8_lossy(line));
+            armored.push('\n');
+        }
+        armored.push_str(SSHSIG_END);
+        armored.push('\n');
+        armored
+    }
+
+    /// Decodes a signature in the armored format of `ssh-keygen`.
+    pub fn from_armored(armored: &str) -> Result<Self> {
+        let armored = armored.trim();
+        let encoded = match armored
+            .strip_prefix(SSHSIG_BEGIN)
+            .and_then(|armored| armored.strip_suffix(SSHSIG_END))
+        {
+            Some(encoded) => encoded,
+            None => {
+                return Err(ToolErrorKind::InvalidData(
+                    "the SSH signature is not armored".to_string(),
+                )
+                .into());
+            }
+        };
+        let encoded: String = encoded.split_whitespace().collect();
+        let blob = base64::decode(encoded)?;
+
+        let mut blob = match blob.strip_prefix(SSHSIG_MAGIC) {
+            Some(blob) => blob,
+            None => {
+                return Err(ToolErrorKind::InvalidData(
+                    "the data is not an SSH signature".to_string(),
+                )
+                .into());
+            }
+        };
+        let version = get_u32(&mut blob)?;
+        if version != SSHSIG_VERSION {
+            return Err(ToolErrorKind::UnsupportedOperation(format!(
+                "SSH signature version {} is not supported",
+                version
+            ))
+            .into());
+        }
+        let public_key = get_string(&mut blob)?.to_vec();
+        let namespace = String::from_utf8_lossy(get_string(&mut blob)?).into_owned();
+        let _reserved = get_string(&mut blob)?;
+        let hash_algorithm = String::from_utf8_lossy(get_string(&mut blob)?).into_owned();
+        let signature = get_string(&mut blob)?.to_vec();
+
+        Ok(SshSignature {
+            public_key,
+            namespace,
+            hash_algorithm,
+            signature,
+        })
+    }
+}
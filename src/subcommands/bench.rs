@@ -0,0 +1,492 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Benchmarks the operations of the providers.
+//!
+//! For each provider and algorithm, the key generation and the operations using the key (signing
+//! and verification, or encryption and decryption) are timed, as well as the random generation.
+//! Latency percentiles and throughput are reported. A failing operation is reported and the
+//! benchmark goes on with the next one. Temporary keys are volatile and deleted whatever the
+//! outcome, and their deletion is not timed.
+
+use crate::cli::OutputFormat;
+use crate::error::{Result, ToolErrorKind};
+use crate::util::{hash_data, TemporaryKey};
+use clap::{Parser, ValueEnum};
+use log::{error, info, warn};
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricEncryption, AsymmetricSignature, Hash,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{
+    Attributes, EccFamily, Lifetime, Policy, Type, UsageFlags,
+};
+use parsec_client::core::interface::requests::{Opcode, ProviderId};
+use parsec_client::BasicClient;
+use serde_json::{json, Value};
+use std::process;
+use std::time::{Duration, Instant};
+
+const MESSAGE: &[u8] = b"Parsec benchmark";
+
+/// Benchmarks the operations of the providers.
+#[derive(Debug, Parser)]
+pub struct Bench {
+    /// Algorithms to benchmark, separated by commas
+    #[structopt(
+        short = 'a',
+        long = "algorithms",
+        value_enum,
+        value_delimiter = ',',
+        default_value = "random,ecdsa-p256,rsa-pkcs1v15-sign,rsa-oaep"
+    )]
+    algorithms: Vec<BenchAlgorithm>,
+
+    /// Number of iterations of the operations
+    #[structopt(short = 'n', long = "iterations", default_value = "100")]
+    iterations: usize,
+
+    /// Number of iterations of the key generation, which is much slower for RSA keys
+    #[structopt(long = "keygen-iterations", default_value = "5")]
+    keygen_iterations: usize,
+
+    /// Size in bits of the RSA keys
+    #[structopt(long = "rsa-key-size", default_value = "2048")]
+    rsa_key_size: usize,
+
+    /// Number of bytes generated by each random generation
+    #[structopt(long = "random-size", default_value = "32")]
+    random_size: usize,
+}
+
+/// Algorithm to benchmark.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum BenchAlgorithm {
+    /// Random generation
+    Random,
+    /// ECDSA signature with SHA-256 on P-256
+    EcdsaP256,
+    /// ECDSA signature with SHA-384 on P-384
+    EcdsaP384,
+    /// RSA PKCS#1 v1.5 signature with SHA-256
+    RsaPkcs1v15Sign,
+    /// RSA PSS signature with SHA-256
+    RsaPss,
+    /// RSA PKCS#1 v1.5 encryption
+    RsaPkcs1v15Crypt,
+    /// RSA OAEP encryption with SHA-256
+    RsaOaep,
+}
+
+impl BenchAlgorithm {
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map_or_else(String::new, |value| value.get_name().to_string())
+    }
+}
+
+/// Latencies of an operation.
+#[derive(Debug)]
+struct Measurement {
+    provider: ProviderId,
+    algorithm: BenchAlgorithm,
+    operation: &'static str,
+    /// Sorted latencies, or the error that stopped the operation
+    latencies: Result<Vec<Duration>>,
+}
+
+impl Measurement {
+    fn new(
+        provider: ProviderId,
+        algorithm: BenchAlgorithm,
+        operation: &'static str,
+        mut latencies: Result<Vec<Duration>>,
+    ) -> Self {
+        if let Ok(latencies) = &mut latencies {
+            latencies.sort();
+        }
+        Measurement {
+            provider,
+            algorithm,
+            operation,
+            latencies,
+        }
+    }
+
+    fn name(&self) -> String {
+        format!("{} {}", self.algorithm.name(), self.operation)
+    }
+}
+
+/// Latency in milliseconds at the given percentile (nearest rank) of sorted latencies.
+fn percentile(latencies: &[Duration], percentile: usize) -> f64 {
+    let rank = (percentile * latencies.len() + 99) / 100;
+    millis(latencies[rank.max(1) - 1])
+}
+
+fn ops_per_sec(latencies: &[Duration]) -> f64 {
+    let total: Duration = latencies.iter().sum();
+    latencies.len() as f64 / total.as_secs_f64()
+}
+
+/// Generates a temporary key, returning it with the time the generation took.
+fn generate_key(
+    basic_client: &BasicClient,
+    name: String,
+    attributes: Attributes,
+) -> Result<(TemporaryKey<'_>, Duration)> {
+    let start = Instant::now();
+    let key = TemporaryKey::generate(basic_client, name, attributes)?;
+    Ok((key, start.elapsed()))
+}
+
+impl Bench {
+    /// Runs the benchmarks and prints the results. Only the given provider is benchmarked, if any.
+    pub fn run(
+        &self,
+        mut basic_client: BasicClient,
+        format: OutputFormat,
+        provider: Option<ProviderId>,
+    ) -> Result<()> {
+        if self.iterations == 0 || self.keygen_iterations == 0 {
+            return Err(ToolErrorKind::InvalidData(
+                "the number of iterations must not be 0".to_string(),
+            )
+            .into());
+        }
+
+        let providers: Vec<ProviderId> = match provider {
+            Some(provider) => vec![provider],
+            None => basic_client
+                .list_providers()?
+                .into_iter()
+                .map(|info| info.id)
+                .filter(|id| *id != ProviderId::Core)
+                .collect(),
+        };
+
+        let mut measurements = Vec::new();
+        let mut failure = None;
+        for provider in providers {
+            basic_client.set_implicit_provider(provider);
+            let opcodes = match basic_client.list_opcodes(provider) {
+                Ok(opcodes) => opcodes,
+                Err(e) => {
+                    error!("Could not list the opcodes of the {}: {}", provider, e);
+                    failure = failure.or(Some(e.into()));
+                    continue;
+                }
+            };
+            for algorithm in &self.algorithms {
+                let needed = needed_opcodes(*algorithm);
+                if let Some(opcode) = needed.iter().find(|opcode| !opcodes.contains(opcode)) {
+                    warn!(
+                        "Skipping {} on the {}, which does not support {:?}",
+                        algorithm.name(),
+                        provider,
+                        opcode
+                    );
+                    continue;
+                }
+                info!("Benchmarking {} on the {}...", algorithm.name(), provider);
+                for (operation, latencies) in self.bench(&basic_client, *algorithm) {
+                    let measurement = Measurement::new(provider, *algorithm, operation, latencies);
+                    if let Err(e) = &measurement.latencies {
+                        warn!("{} failed on the {}: {}", measurement.name(), provider, e);
+                    }
+                    measurements.push(measurement);
+                }
+            }
+        }
+
+        match format {
+            OutputFormat::Text => print_text(&measurements),
+            OutputFormat::Json => print_json(&measurements),
+        }
+
+        match failure.or_else(|| {
+            measurements
+                .into_iter()
+                .find_map(|measurement| measurement.latencies.err())
+        }) {
+            None => Ok(()),
+            Some(e) => {
+                error!("Some operations failed");
+                Err(e)
+            }
+        }
+    }
+
+    /// Returns the latencies of the operations of the algorithm. The operations needing the
+    /// result of a failed one are not run.
+    fn bench(
+        &self,
+        basic_client: &BasicClient,
+        algorithm: BenchAlgorithm,
+    ) -> Vec<(&'static str, Result<Vec<Duration>>)> {
+        if algorithm == BenchAlgorithm::Random {
+            let latencies = time(self.iterations, || {
+                let _ = basic_client.psa_generate_random(self.random_size)?;
+                Ok(())
+            });
+            return vec![("generate-random", latencies)];
+        }
+
+        let attributes = self.attributes(algorithm);
+        let key_name = |index: usize| {
+            format!(
+                "parsec-tool-bench-{}-{}-{}",
+                process::id(),
+                algorithm.name(),
+                index
+            )
+        };
+        let mut keygen_latencies = Vec::new();
+        let mut temporary_key = None;
+        // The last key generated is used by the other operations.
+        for index in 0..self.keygen_iterations {
+            match generate_key(basic_client, key_name(index), attributes) {
+                Ok((key, latency)) => {
+                    keygen_latencies.push(latency);
+                    temporary_key = Some(key);
+                }
+                Err(e) => return vec![("generate-key", Err(e))],
+            }
+        }
+        let temporary_key = match temporary_key {
+            Some(temporary_key) => temporary_key,
+            None => return Vec::new(),
+        };
+        let key = temporary_key.name();
+
+        let mut results = vec![("generate-key", Ok(keygen_latencies))];
+        match attributes.policy.permitted_algorithms {
+            Algorithm::AsymmetricSignature(alg) => {
+                let hash = if algorithm == BenchAlgorithm::EcdsaP384 {
+                    Hash::Sha384
+                } else {
+                    Hash::Sha256
+                };
+                let hash = match hash_data(MESSAGE, hash) {
+                    Ok(hash) => hash,
+                    Err(e) => {
+                        results.push(("sign", Err(e)));
+                        return results;
+                    }
+                };
+                let mut signature = Vec::new();
+                let sign = time(self.iterations, || {
+                    signature = basic_client.psa_sign_hash(key, &hash, alg)?;
+                    Ok(())
+                });
+                let signed = sign.is_ok();
+                results.push(("sign", sign));
+                if signed {
+                    results.push((
+                        "verify",
+                        time(self.iterations, || {
+                            basic_client.psa_verify_hash(key, &hash, alg, &signature)?;
+                            Ok(())
+                        }),
+                    ));
+                }
+            }
+            Algorithm::AsymmetricEncryption(alg) => {
+                let mut ciphertext = Vec::new();
+                let encrypt = time(self.iterations, || {
+                    ciphertext = basic_client.psa_asymmetric_encrypt(key, alg, MESSAGE, None)?;
+                    Ok(())
+                });
+                let encrypted = encrypt.is_ok();
+                results.push(("encrypt", encrypt));
+                if encrypted {
+                    results.push((
+                        "decrypt",
+                        time(self.iterations, || {
+                            let _ =
+                                basic_client.psa_asymmetric_decrypt(key, alg, &ciphertext, None)?;
+                            Ok(())
+                        }),
+                    ));
+                }
+            }
+            _ => unreachable!(),
+        }
+        results
+    }
+
+    fn attributes(&self, algorithm: BenchAlgorithm) -> Attributes {
+        let ecc_key_pair = Type::EccKeyPair {
+            curve_family: EccFamily::SecpR1,
+        };
+        let (key_type, bits, permitted_algorithms): (Type, usize, Algorithm) = match algorithm {
+            BenchAlgorithm::EcdsaP256 => (
+                ecc_key_pair,
+                256,
+                AsymmetricSignature::Ecdsa {
+                    hash_alg: Hash::Sha256.into(),
+                }
+                .into(),
+            ),
+            BenchAlgorithm::EcdsaP384 => (
+                ecc_key_pair,
+                384,
+                AsymmetricSignature::Ecdsa {
+                    hash_alg: Hash::Sha384.into(),
+                }
+                .into(),
+            ),
+            BenchAlgorithm::RsaPkcs1v15Sign => (
+                Type::RsaKeyPair,
+                self.rsa_key_size,
+                AsymmetricSignature::RsaPkcs1v15Sign {
+                    hash_alg: Hash::Sha256.into(),
+                }
+                .into(),
+            ),
+            BenchAlgorithm::RsaPss => (
+                Type::RsaKeyPair,
+                self.rsa_key_size,
+                AsymmetricSignature::RsaPss {
+                    hash_alg: Hash::Sha256.into(),
+                }
+                .into(),
+            ),
+            BenchAlgorithm::RsaPkcs1v15Crypt => (
+                Type::RsaKeyPair,
+                self.rsa_key_size,
+                AsymmetricEncryption::RsaPkcs1v15Crypt.into(),
+            ),
+            BenchAlgorithm::RsaOaep => (
+                Type::RsaKeyPair,
+                self.rsa_key_size,
+                AsymmetricEncryption::RsaOaep {
+                    hash_alg: Hash::Sha256,
+                }
+                .into(),
+            ),
+            BenchAlgorithm::Random => unreachable!(),
+        };
+
+        let mut usage_flags = UsageFlags::default();
+        let _ = usage_flags
+            .set_sign_hash()
+            .set_verify_hash()
+            .set_encrypt()
+            .set_decrypt();
+        Attributes {
+            lifetime: Lifetime::Volatile,
+            key_type,
+            bits,
+            policy: Policy {
+                usage_flags,
+                permitted_algorithms,
+            },
+        }
+    }
+}
+
+fn needed_opcodes(algorithm: BenchAlgorithm) -> &'static [Opcode] {
+    match algorithm {
+        BenchAlgorithm::Random => &[Opcode::PsaGenerateRandom],
+        BenchAlgorithm::EcdsaP256
+        | BenchAlgorithm::EcdsaP384
+        | BenchAlgorithm::RsaPkcs1v15Sign
+        | BenchAlgorithm::RsaPss => &[
+            Opcode::PsaGenerateKey,
+            Opcode::PsaDestroyKey,
+            Opcode::PsaSignHash,
+            Opcode::PsaVerifyHash,
+        ],
+        BenchAlgorithm::RsaPkcs1v15Crypt | BenchAlgorithm::RsaOaep => &[
+            Opcode::PsaGenerateKey,
+            Opcode::PsaDestroyKey,
+            Opcode::PsaAsymmetricEncrypt,
+            Opcode::PsaAsymmetricDecrypt,
+        ],
+    }
+}
+
+/// Times the iterations of the operation.
+fn time(iterations: usize, mut operation: impl FnMut() -> Result<()>) -> Result<Vec<Duration>> {
+    (0..iterations)
+        .map(|_| {
+            let start = Instant::now();
+            operation()?;
+            Ok(start.elapsed())
+        })
+        .collect()
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn print_text(measurements: &[Measurement]) {
+    let mut provider = None;
+    for measurement in measurements {
+        if provider != Some(measurement.provider) {
+            provider = Some(measurement.provider);
+            println!();
+            println!(
+                "0x{:02x} ({})",
+                measurement.provider as u32, measurement.provider
+            );
+            println!(
+                "{:<36}{:>8}{:>11}{:>11}{:>11}{:>11}{:>11}{:>11}",
+                "operation", "count", "min ms", "p50 ms", "p90 ms", "p99 ms", "max ms", "ops/s"
+            );
+        }
+        match &measurement.latencies {
+            Ok(latencies) => println!(
+                "{:<36}{:>8}{:>11.3}{:>11.3}{:>11.3}{:>11.3}{:>11.3}{:>11.1}",
+                measurement.name(),
+                latencies.len(),
+                millis(latencies[0]),
+                percentile(latencies, 50),
+                percentile(latencies, 90),
+                percentile(latencies, 99),
+                millis(latencies[latencies.len() - 1]),
+                ops_per_sec(latencies)
+            ),
+            Err(e) => println!(
+                "{:<36}{:>8}  FAIL ({})",
+                measurement.name(),
+                "-",
+                e.status_or_message()
+            ),
+        }
+    }
+}
+
+fn print_json(measurements: &[Measurement]) {
+    let measurements: Vec<Value> = measurements
+        .iter()
+        .map(|measurement| {
+            let mut json = json!({
+                "provider": {
+                    "id": measurement.provider as u8,
+                    "name": measurement.provider.to_string(),
+                },
+                "algorithm": measurement.algorithm.name(),
+                "operation": measurement.operation,
+            });
+            let results = match &measurement.latencies {
+                Ok(latencies) => json!({
+                    "iterations": latencies.len(),
+                    "min_ms": millis(latencies[0]),
+                    "p50_ms": percentile(latencies, 50),
+                    "p90_ms": percentile(latencies, 90),
+                    "p99_ms": percentile(latencies, 99),
+                    "max_ms": millis(latencies[latencies.len() - 1]),
+                    "ops_per_sec": ops_per_sec(latencies),
+                }),
+                Err(e) => json!({ "status": e.status_or_message() }),
+            };
+            if let (Some(json), Value::Object(results)) = (json.as_object_mut(), results) {
+                json.extend(results);
+            }
+            json
+        })
+        .collect();
+    println!("{}", Value::from(measurements));
+}
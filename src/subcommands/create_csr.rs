@@ -4,9 +4,8 @@
 //! Creates a Certificate Signing Request (CSR) from a keypair.
 
 use crate::error::{Error, Result, ToolErrorKind};
-use crate::util::sign_message_with_policy;
+use crate::util::{sign_message_with_policy, HashAlgorithm, SignatureEncoding};
 use clap::Parser;
-use log::error;
 use parsec_client::core::interface::operations::psa_algorithm::{
     Algorithm, AsymmetricSignature, Hash, SignHash,
 };
@@ -15,9 +14,12 @@ use parsec_client::BasicClient;
 use rcgen::Error as RcgenError;
 use rcgen::{
     CertificateParams, DistinguishedName, DnType, KeyPair, RemoteKeyPair, SignatureAlgorithm,
-    PKCS_ECDSA_P256_SHA256, PKCS_ECDSA_P384_SHA384, PKCS_RSA_PSS_SHA256, PKCS_RSA_PSS_SHA384,
+    PKCS_ECDSA_P256_SHA256, PKCS_ECDSA_P256_SHA384, PKCS_ECDSA_P256_SHA512, PKCS_ECDSA_P384_SHA256,
+    PKCS_ECDSA_P384_SHA384, PKCS_ECDSA_P384_SHA512, PKCS_ECDSA_P521_SHA256, PKCS_ECDSA_P521_SHA384,
+    PKCS_ECDSA_P521_SHA512, PKCS_RSA_PSS_SHA256, PKCS_RSA_PSS_SHA384, PKCS_RSA_PSS_SHA512,
     PKCS_RSA_SHA256, PKCS_RSA_SHA384, PKCS_RSA_SHA512,
 };
+use std::sync::{Arc, Mutex};
 
 /// Creates an X509 Certificate Signing Request (CSR) from a keypair, using the signing algorithm
 /// that is associated with the key.
@@ -28,10 +30,16 @@ pub struct CreateCsr {
     /// The name of the key to use for signing. This must be an existing key that is accessible
     /// to the user, and it must be a signing key (either an RSA key or an elliptic curve key).
     ///
-    /// Elliptic curve keys must use the NIST P256 or P384 curves.
+    /// Elliptic curve keys must use the NIST P256, P384 or P521 curves.
     #[structopt(short = 'k', long = "key-name")]
     key_name: String,
 
+    /// The hash algorithm used to sign the CSR. This can only be chosen freely if the key permits
+    /// any hashing algorithm; otherwise it must match the one required by the key policy. Defaults
+    /// to the key's own hash algorithm, or to SHA-256 if the key permits any.
+    #[structopt(long = "hash")]
+    hash: Option<HashAlgorithm>,
+
     /// The common name to be used within the Distinguished Name (DN) specification of
     /// the CSR.
     #[structopt(long = "cn")]
@@ -76,6 +84,9 @@ struct ParsecRemoteKeyPair {
     public_key_der: Vec<u8>,
     parsec_client: BasicClient,
     rcgen_algorithm: &'static SignatureAlgorithm,
+    hash: Hash,
+    // rcgen only reports that the remote signature failed: the error is kept here.
+    sign_error: Arc<Mutex<Option<Error>>>,
 }
 
 impl CreateCsr {
@@ -83,14 +94,17 @@ impl CreateCsr {
     pub fn run(&self, basic_client: BasicClient) -> Result<()> {
         let public_key = basic_client.psa_export_public_key(&self.key_name)?;
 
-        let rcgen_algorithm = self.get_rcgen_algorithm(&basic_client)?;
+        let (rcgen_algorithm, hash) = self.get_rcgen_algorithm(&basic_client)?;
 
+        let sign_error = Arc::new(Mutex::new(None));
         let parsec_key_pair = ParsecRemoteKeyPair {
             key_name: self.key_name.clone(),
             public_key_der: public_key,
             // "Move" the client into the struct here.
             parsec_client: basic_client,
             rcgen_algorithm,
+            hash,
+            sign_error: sign_error.clone(),
         };
 
         let remote_key_pair = KeyPair::from_remote(Box::new(parsec_key_pair))?;
@@ -143,8 +157,22 @@ impl CreateCsr {
         let mut params = CertificateParams::new(subject_alt_names)?;
         params.distinguished_name = dn;
 
-        let cert = params.self_signed(&remote_key_pair)?;
-        let csr = cert.params().serialize_request(&remote_key_pair)?;
+        // Report the error of the Parsec signature rather than the one of rcgen.
+        let signing_error = |error: RcgenError| -> Error {
+            if error == RcgenError::RemoteKeyError {
+                if let Some(error) = sign_error.lock().ok().and_then(|mut error| error.take()) {
+                    return error;
+                }
+            }
+            error.into()
+        };
+        let cert = params
+            .self_signed(&remote_key_pair)
+            .map_err(signing_error)?;
+        let csr = cert
+            .params()
+            .serialize_request(&remote_key_pair)
+            .map_err(signing_error)?;
 
         let pem_string = csr.pem()?;
         println!("{}", pem_string);
@@ -153,102 +181,107 @@ impl CreateCsr {
     }
 
     // Inspect the attributes of the signing key and map them down to one of rcgen's supported hash-and-sign
-    // schemes (throwing an error if there isn't a suitable mapping).
+    // schemes (throwing an error if there isn't a suitable mapping). The hash algorithm that must be used
+    // for signing is returned alongside, so that the signature matches the advertised algorithm.
     //
     // There's rather a lot of complexity here, because we need to map down lots of nested PSA properties onto a small number
     // of hash-and-sign schemes that RCGEN supports.
     fn get_rcgen_algorithm(
         &self,
         basic_client: &BasicClient,
-    ) -> Result<&'static SignatureAlgorithm> {
+    ) -> Result<(&'static SignatureAlgorithm, Hash)> {
         let attributes = basic_client.key_attributes(&self.key_name)?;
 
-        if let Algorithm::AsymmetricSignature(alg) = attributes.policy.permitted_algorithms {
-            match alg {
-                AsymmetricSignature::RsaPkcs1v15Sign { hash_alg } => match hash_alg {
-                    SignHash::Specific(Hash::Sha256) => Ok(&PKCS_RSA_SHA256),
-                    SignHash::Specific(Hash::Sha384) => Ok(&PKCS_RSA_SHA384),
-                    SignHash::Specific(Hash::Sha512) => Ok(&PKCS_RSA_SHA512),
-                    SignHash::Any => Ok(&PKCS_RSA_SHA256), // Default hash algorithm for the tool.
-                    _ => {
-                        // The algorithm is specific, but not one that RCGEN can use, so fail the operation.
-                        error!("Signing key requires use of hashing algorithm ({:?}), which is not supported for certificate requests.", alg);
-                        Err(ToolErrorKind::NotSupported.into())
-                    }
-                },
-                AsymmetricSignature::RsaPkcs1v15SignRaw => {
-                    // Key policy specifies raw RSA signatures. RCGEN will always hash-and-sign, so fail.
-                    error!("Signing key specifies raw signing only, which is not supported for certificate requests.");
-                    Err(ToolErrorKind::NotSupported.into())
+        let alg =
+            if let Algorithm::AsymmetricSignature(alg) = attributes.policy.permitted_algorithms {
+                alg
+            } else {
+                return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                    key_name: self.key_name.clone(),
+                    expected: "an asymmetric signature algorithm".to_string(),
+                    actual: format!("{:?}", attributes.policy.permitted_algorithms),
                 }
-                AsymmetricSignature::RsaPss { hash_alg } => match hash_alg {
-                    SignHash::Specific(Hash::Sha256) => Ok(&PKCS_RSA_PSS_SHA256),
-                    SignHash::Specific(Hash::Sha384) => Ok(&PKCS_RSA_PSS_SHA384),
-                    SignHash::Any => Ok(&PKCS_RSA_PSS_SHA256), // Default hash algorithm for the tool.
-                    _ => {
-                        // The algorithm is specific, but not one that RCGEN can use, so fail the operation.
-                        error!("Signing key requires use of hashing algorithm ({:?}), which is not supported for certificate requests.", alg);
-                        Err(ToolErrorKind::NotSupported.into())
-                    }
-                },
-                AsymmetricSignature::Ecdsa { hash_alg } => {
-                    if !matches!(
-                        attributes.key_type,
-                        Type::EccKeyPair {
-                            curve_family: EccFamily::SecpR1
-                        }
-                    ) {
-                        error!(
-                            "Signing key must use curve family SecpR1 for certificate requests."
-                        );
-                        return Err(ToolErrorKind::NotSupported.into());
-                    };
-
-                    match hash_alg {
-                        SignHash::Specific(Hash::Sha256) => {
-                            if attributes.bits == 256 {
-                                Ok(&PKCS_ECDSA_P256_SHA256)
-                            } else {
-                                error!("Signing key should have strength 256, but actually has strength {}.", attributes.bits);
-                                Err(ToolErrorKind::NotSupported.into())
-                            }
-                        }
-                        SignHash::Specific(Hash::Sha384) => {
-                            if attributes.bits == 384 {
-                                Ok(&PKCS_ECDSA_P384_SHA384)
-                            } else {
-                                error!("Signing key should have strength 384, but actually has strength {}.", attributes.bits);
-                                Err(ToolErrorKind::NotSupported.into())
-                            }
-                        }
-                        SignHash::Any => {
-                            match attributes.bits {
-                                256 => Ok(&PKCS_ECDSA_P256_SHA256),
-                                _ => {
-                                    // We have to fail this, because ParsecRemoteKeyPair::sign() defaults the hash to SHA-256, and RCGEN
-                                    // doesn't support a hash algorithm that is different from the key strength.
-                                    error!("Signing keys of strength other than 256-bit not supported without specific hash algorithm.");
-                                    Err(ToolErrorKind::NotSupported.into())
-                                }
-                            }
-                        }
-                        _ => {
-                            // The algorithm is specific, but not one that RCGEN can use, so fail the operation.
-                            error!("Signing key requires use of hashing algorithm ({:?}), which is not supported for certificate requests.", alg);
-                            Err(ToolErrorKind::NotSupported.into())
+                .into());
+            };
+
+        let hash = match alg.hash() {
+            Some(SignHash::Specific(hash)) => {
+                if let Some(requested) = self.hash {
+                    if Hash::from(requested) != hash {
+                        return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                            key_name: self.key_name.clone(),
+                            expected: format!("{:?}", Hash::from(requested)),
+                            actual: format!("{:?}", hash),
                         }
+                        .into());
                     }
                 }
-                _ => {
-                    // Unsupported algorithm.
-                    error!("The specified key is not supported for certificate requests.");
-                    Err(ToolErrorKind::NotSupported.into())
+                hash
+            }
+            // Default hash algorithm for the tool. Raw RSA signatures are made over the DigestInfo
+            // of the hash, which gives the same signatures as hash-and-sign PKCS#1 v1.5.
+            Some(SignHash::Any) | None if alg != AsymmetricSignature::EcdsaAny => {
+                self.hash.map_or(Hash::Sha256, Hash::from)
+            }
+            _ => return self.unsupported(format!("{:?}", alg)),
+        };
+
+        let rcgen_algorithm: &'static SignatureAlgorithm = match alg {
+            AsymmetricSignature::RsaPkcs1v15Sign { .. }
+            | AsymmetricSignature::RsaPkcs1v15SignRaw => match hash {
+                Hash::Sha256 => &PKCS_RSA_SHA256,
+                Hash::Sha384 => &PKCS_RSA_SHA384,
+                Hash::Sha512 => &PKCS_RSA_SHA512,
+                _ => return self.unsupported(format!("{:?}", hash)),
+            },
+            AsymmetricSignature::RsaPss { .. } => match hash {
+                Hash::Sha256 => &PKCS_RSA_PSS_SHA256,
+                Hash::Sha384 => &PKCS_RSA_PSS_SHA384,
+                Hash::Sha512 => &PKCS_RSA_PSS_SHA512,
+                _ => return self.unsupported(format!("{:?}", hash)),
+            },
+            AsymmetricSignature::Ecdsa { .. } => {
+                if !matches!(
+                    attributes.key_type,
+                    Type::EccKeyPair {
+                        curve_family: EccFamily::SecpR1
+                    }
+                ) {
+                    return self.unsupported(format!("{:?}", attributes.key_type));
+                };
+
+                match (attributes.bits, hash) {
+                    (256, Hash::Sha256) => &PKCS_ECDSA_P256_SHA256,
+                    (256, Hash::Sha384) => &PKCS_ECDSA_P256_SHA384,
+                    (256, Hash::Sha512) => &PKCS_ECDSA_P256_SHA512,
+                    (384, Hash::Sha256) => &PKCS_ECDSA_P384_SHA256,
+                    (384, Hash::Sha384) => &PKCS_ECDSA_P384_SHA384,
+                    (384, Hash::Sha512) => &PKCS_ECDSA_P384_SHA512,
+                    (521, Hash::Sha256) => &PKCS_ECDSA_P521_SHA256,
+                    (521, Hash::Sha384) => &PKCS_ECDSA_P521_SHA384,
+                    (521, Hash::Sha512) => &PKCS_ECDSA_P521_SHA512,
+                    (256, _) | (384, _) | (521, _) => {
+                        return self.unsupported(format!("{:?}", hash))
+                    }
+                    (bits, _) => return self.unsupported(format!("ECDSA with {} bits", bits)),
                 }
             }
-        } else {
-            error!("Specified key is not an asymmetric signing key, which is needed for certificate requests.");
-            Err(ToolErrorKind::WrongKeyAlgorithm.into())
+            // Unsupported algorithm.
+            _ => return self.unsupported(format!("{:?}", alg)),
+        };
+
+        Ok((rcgen_algorithm, hash))
+    }
+
+    // The key, or the hash it must be used with, is not one that RCGEN can use, so fail the
+    // operation.
+    fn unsupported(&self, algorithm: String) -> Result<(&'static SignatureAlgorithm, Hash)> {
+        Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+            key_name: self.key_name.clone(),
+            algorithm,
+            operation: "certificate requests".to_string(),
         }
+        .into())
     }
 }
 
@@ -258,9 +291,19 @@ impl RemoteKeyPair for ParsecRemoteKeyPair {
     }
 
     fn sign(&self, msg: &[u8]) -> std::result::Result<Vec<u8>, RcgenError> {
-        let signature =
-            sign_message_with_policy(&self.parsec_client, &self.key_name, msg, Some(Hash::Sha256))
-                .map_err(RcgenError::from)?;
+        let signature = sign_message_with_policy(
+            &self.parsec_client,
+            &self.key_name,
+            msg,
+            Some(self.hash),
+            SignatureEncoding::Der,
+        )
+        .map_err(|error| {
+            if let Ok(mut sign_error) = self.sign_error.lock() {
+                *sign_error = Some(error);
+            }
+            RcgenError::RemoteKeyError
+        })?;
         Ok(signature)
     }
 
@@ -268,14 +311,3 @@ impl RemoteKeyPair for ParsecRemoteKeyPair {
         self.rcgen_algorithm
     }
 }
-
-impl From<Error> for RcgenError {
-    fn from(_e: Error) -> Self {
-        // There isn't a suitable mapping, because RcgenError does not have a variant for the
-        // case where RemoteKeyPair failed for third-party reasons.
-        // See: https://github.com/est31/rcgen/issues/67
-        // The crate will publish a new enum variant. When this change is released, we can rework this to be a
-        // more suitable error.
-        RcgenError::KeyGenerationUnavailable
-    }
-}
@@ -3,11 +3,15 @@
 
 //! Create an ECC key pair.
 //!
-use crate::error::Result;
+//! The key is on one of the NIST curves: P-256 (default), P-384 or P-521. Signing keys use ECDSA
+//! with the hash of the same strength as the curve.
+use crate::error::{Result, ToolErrorKind};
 use clap::Parser;
 use log::info;
-/// The curve will be secp256r1. Used by default for asymmetric signing with ECDSA (SHA-256).
-use parsec_client::core::interface::operations::psa_algorithm::{AsymmetricSignature, Hash};
+/// The curve is secp256r1 by default. Used by default for asymmetric signing with ECDSA (SHA-256).
+use parsec_client::core::interface::operations::psa_algorithm::{
+    AsymmetricSignature, Hash, KeyAgreement, RawKeyAgreement,
+};
 use parsec_client::core::interface::operations::psa_key_attributes::{
     Attributes, EccFamily, Lifetime, Policy, Type, UsageFlags,
 };
@@ -18,20 +22,48 @@ use parsec_client::BasicClient;
 pub struct CreateEccKey {
     #[structopt(short = 'k', long = "key-name")]
     key_name: String,
+
+    /// Supply this flag to create a key for ECDH key agreement, used for ECIES encryption, instead
+    /// of a signing key.
+    #[structopt(short = 'a', long = "for-key-agreement")]
+    is_for_key_agreement: bool,
+
+    /// Specifies the size (strength) of the key in bits: 256, 384 or 521 for the P-256, P-384 or
+    /// P-521 curves. The default size for ECC keys is 256 bits.
+    #[structopt(short = 'b', long = "bits")]
+    bits: Option<usize>,
 }
 
 impl CreateEccKey {
     /// Exports a key.
     pub fn run(&self, basic_client: BasicClient) -> Result<()> {
-        info!("Creating ECC signing key...");
+        let bits = self.bits.unwrap_or(256);
+        let hash = match bits {
+            256 => Hash::Sha256,
+            384 => Hash::Sha384,
+            521 => Hash::Sha512,
+            _ => {
+                return Err(ToolErrorKind::UnsupportedOperation(format!(
+                    "ECC keys of {} bits are not supported (use 256, 384 or 521)",
+                    bits
+                ))
+                .into());
+            }
+        };
 
-        let attributes = Attributes {
-            lifetime: Lifetime::Persistent,
-            key_type: Type::EccKeyPair {
-                curve_family: EccFamily::SecpR1,
-            },
-            bits: 256,
-            policy: Policy {
+        let policy = if self.is_for_key_agreement {
+            info!("Creating ECC key agreement key...");
+            Policy {
+                usage_flags: {
+                    let mut usage_flags = UsageFlags::default();
+                    let _ = usage_flags.set_derive();
+                    usage_flags
+                },
+                permitted_algorithms: KeyAgreement::Raw(RawKeyAgreement::Ecdh).into(),
+            }
+        } else {
+            info!("Creating ECC signing key...");
+            Policy {
                 usage_flags: {
                     let mut usage_flags = UsageFlags::default();
                     let _ = usage_flags
@@ -42,10 +74,19 @@ impl CreateEccKey {
                     usage_flags
                 },
                 permitted_algorithms: AsymmetricSignature::Ecdsa {
-                    hash_alg: Hash::Sha256.into(),
+                    hash_alg: hash.into(),
                 }
                 .into(),
+            }
+        };
+
+        let attributes = Attributes {
+            lifetime: Lifetime::Persistent,
+            key_type: Type::EccKeyPair {
+                curve_family: EccFamily::SecpR1,
             },
+            bits,
+            policy,
         };
 
         basic_client.psa_generate_key(&self.key_name, attributes)?;
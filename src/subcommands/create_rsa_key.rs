@@ -6,6 +6,7 @@
 //! The key will be 2048 bits long. Used by default for asymmetric encryption with RSA PKCS#1 v1.5.
 
 use crate::error::Result;
+use crate::util::HashAlgorithm;
 use clap::Parser;
 use log::info;
 use parsec_client::core::interface::operations::psa_algorithm::{
@@ -32,14 +33,26 @@ pub struct CreateRsaKey {
     #[structopt(short = 'r', long = "for-signing-pss")]
     is_for_signing_pss: bool,
 
+    /// Supply this flag to create a signing key with PKCS#1 v1.5 scheme for raw signatures, of
+    /// digests hashed with any algorithm.
+    #[structopt(
+        long = "for-signing-raw",
+        conflicts_with_all = ["is_for_signing", "is_for_signing_pss", "oaep", "oaep_hash"]
+    )]
+    is_for_signing_raw: bool,
+
     /// Specifies the size (strength) of the key in bits. The default size for RSA keys is 2048 bits.
     #[structopt(short = 'b', long = "bits")]
     bits: Option<usize>,
 
-    /// Specifies if the RSA key should be created with permitted RSA OAEP (SHA256) encryption algorithm
-    /// instead of the default RSA PKCS#1 v1.5 one.
+    /// Specifies if the RSA key should be created with permitted RSA OAEP (SHA256 by default) encryption
+    /// algorithm instead of the default RSA PKCS#1 v1.5 one.
     #[structopt(short = 'o', long = "oaep")]
     oaep: bool,
+
+    /// Hash algorithm of RSA OAEP encryption (SHA-256 by default)
+    #[structopt(long = "oaep-hash", requires = "oaep")]
+    oaep_hash: Option<HashAlgorithm>,
 }
 
 impl CreateRsaKey {
@@ -80,6 +93,16 @@ impl CreateRsaKey {
                 }
                 .into(),
             }
+        } else if self.is_for_signing_raw {
+            info!("Creating RSA signing key with raw PKCS1 v1.5 scheme...");
+            Policy {
+                usage_flags: {
+                    let mut usage_flags = UsageFlags::default();
+                    let _ = usage_flags.set_sign_hash().set_verify_hash();
+                    usage_flags
+                },
+                permitted_algorithms: AsymmetricSignature::RsaPkcs1v15SignRaw.into(),
+            }
         } else {
             info!("Creating RSA encryption key...");
             Policy {
@@ -90,7 +113,7 @@ impl CreateRsaKey {
                 },
                 permitted_algorithms: if self.oaep {
                     AsymmetricEncryption::RsaOaep {
-                        hash_alg: Hash::Sha256,
+                        hash_alg: self.oaep_hash.map_or(Hash::Sha256, Hash::from),
                     }
                     .into()
                 } else {
@@ -3,13 +3,27 @@
 
 //! Decrypts data.
 //!
-//! Will use the algorithm set to the key's policy during creation.
+//! Will use the algorithm set to the key's policy during creation. The label of RSA-OAEP
+//! encryption, if any, must be given again.
+//!
+//! Envelopes made by `encrypt --envelope` are recognised: their content key is decrypted with the
+//! key, and the payload with the content key. The payload is output as is. ECC keys allowing ECDH
+//! decrypt ECIES envelopes, whose content key is derived from an ECDH exchange with the key.
+//!
+//! Input files can hold the binary ciphertext or envelope, or the base64 output of `encrypt`.
 
+use crate::envelope::{ecies_content_key, Envelope, KeyWrapping};
 use crate::error::{Result, ToolErrorKind};
+use crate::util::{check_label, read_label, write_binary};
 use clap::Parser;
-use log::{error, info};
-use parsec_client::core::interface::operations::psa_algorithm::Algorithm;
+use log::info;
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricEncryption, KeyAgreement, RawKeyAgreement,
+};
 use parsec_client::BasicClient;
+use std::fs;
+use std::path::PathBuf;
+use zeroize::Zeroizing;
 
 /// Decrypts data.
 #[derive(Debug, Parser)]
@@ -18,37 +32,79 @@ pub struct Decrypt {
     key_name: String,
 
     /// Ciphertext base64 encoded
-    input_data: String,
+    #[structopt(required_unless_present = "input_file")]
+    input_data: Option<String>,
+
+    /// File containing the ciphertext (binary or base64) or the envelope, instead of a base64
+    /// string
+    #[structopt(short = 'i', long = "input-file", conflicts_with = "input_data")]
+    input_file: Option<PathBuf>,
+
+    /// Label of RSA-OAEP encryption, as a string
+    #[structopt(short = 'l', long = "label")]
+    label: Option<String>,
+
+    /// Label of RSA-OAEP encryption, in hexadecimal
+    #[structopt(long = "label-hex", conflicts_with = "label")]
+    label_hex: Option<String>,
+
+    /// File containing the label of RSA-OAEP encryption
+    #[structopt(long = "label-file", conflicts_with_all = ["label", "label_hex"])]
+    label_file: Option<PathBuf>,
 }
 
 impl Decrypt {
     /// Decrypts data.
     pub fn run(&self, basic_client: BasicClient) -> Result<()> {
-        let input = base64::decode(self.input_data.as_bytes())?;
+        let input = match (&self.input_data, &self.input_file) {
+            (Some(input_data), None) => base64::decode(input_data.trim())?,
+            (None, Some(input_file)) => ciphertext_file(fs::read(input_file)?),
+            _ => {
+                return Err(ToolErrorKind::MissingInput(
+                    "either input data or an input file must be given".to_string(),
+                )
+                .into());
+            }
+        };
 
         let alg = basic_client
             .key_attributes(&self.key_name)?
             .policy
             .permitted_algorithms;
+        let label = read_label(&self.label, &self.label_hex, &self.label_file)?;
+        check_label(&self.key_name, alg, &label)?;
 
         let plaintext = match alg {
+            Algorithm::KeyAgreement(KeyAgreement::Raw(RawKeyAgreement::Ecdh)) => {
+                return self.decrypt_ecies(&basic_client, &input);
+            }
+            Algorithm::AsymmetricEncryption(alg) if Envelope::is_envelope(&input) => {
+                return self.decrypt_envelope(&basic_client, alg, label.as_deref(), &input);
+            }
             Algorithm::AsymmetricEncryption(alg) => {
                 info!("Decrypting data with {:?}...", alg);
-                basic_client.psa_asymmetric_decrypt(&self.key_name, alg, &input, None)?
+                basic_client.psa_asymmetric_decrypt(
+                    &self.key_name,
+                    alg,
+                    &input,
+                    label.as_deref(),
+                )?
             }
             Algorithm::Cipher(_) | Algorithm::Aead(_) => {
-                error!(
-                    "Key's algorithm is {:?} which is not currently supported for decryption.",
-                    alg
-                );
-                return Err(ToolErrorKind::NotSupported.into());
+                return Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+                    key_name: self.key_name.clone(),
+                    algorithm: format!("{:?}", alg),
+                    operation: "decryption".to_string(),
+                }
+                .into());
             }
             other => {
-                error!(
-                    "Key's algorithm is {:?} which can not be used for decryption.",
-                    other
-                );
-                return Err(ToolErrorKind::WrongKeyAlgorithm.into());
+                return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                    key_name: self.key_name.clone(),
+                    expected: "an asymmetric encryption algorithm".to_string(),
+                    actual: format!("{:?}", other),
+                }
+                .into());
             }
         };
 
@@ -58,4 +114,75 @@ impl Decrypt {
 
         Ok(())
     }
+
+    fn decrypt_envelope(
+        &self,
+        basic_client: &BasicClient,
+        alg: AsymmetricEncryption,
+        label: Option<&[u8]>,
+        input: &[u8],
+    ) -> Result<()> {
+        let envelope = Envelope::decode(input)?;
+        match (envelope.key_wrapping, alg) {
+            (KeyWrapping::RsaOaep, AsymmetricEncryption::RsaOaep { .. }) => (),
+            (key_wrapping, alg) => {
+                // The content key is wrapped with another algorithm than the key's one.
+                return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                    key_name: self.key_name.clone(),
+                    expected: format!("{:?}", key_wrapping),
+                    actual: format!("{:?}", alg),
+                }
+                .into());
+            }
+        }
+
+        info!("Unwrapping the content key with {:?}...", alg);
+        let content_key = Zeroizing::new(basic_client.psa_asymmetric_decrypt(
+            &self.key_name,
+            alg,
+            &envelope.wrapped_key,
+            label,
+        )?);
+
+        write_binary(&envelope.open(&content_key)?)
+    }
+
+    fn decrypt_ecies(&self, basic_client: &BasicClient, input: &[u8]) -> Result<()> {
+        let envelope = Envelope::decode(input)?;
+        if envelope.key_wrapping != KeyWrapping::Ecies {
+            // The key can only be used with ECIES.
+            return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                key_name: self.key_name.clone(),
+                expected: format!("{:?}", envelope.key_wrapping),
+                actual: format!("{:?}", RawKeyAgreement::Ecdh),
+            }
+            .into());
+        }
+
+        info!("Deriving the content key with ECDH...");
+        let shared_secret = Zeroizing::new(basic_client.psa_raw_key_agreement(
+            RawKeyAgreement::Ecdh,
+            &self.key_name,
+            &envelope.wrapped_key,
+        )?);
+        let public_key = basic_client.psa_export_public_key(&self.key_name)?;
+        let content_key = ecies_content_key(&shared_secret, &envelope.wrapped_key, &public_key)?;
+
+        write_binary(&envelope.open(&content_key)?)
+    }
+}
+
+// Decodes the content of a ciphertext file if it is base64 text, as written by `encrypt` without
+// `--envelope`. Envelopes and binary ciphertexts are returned as is.
+fn ciphertext_file(content: Vec<u8>) -> Vec<u8> {
+    if Envelope::is_envelope(&content) {
+        return content;
+    }
+    match std::str::from_utf8(&content)
+        .ok()
+        .and_then(|text| base64::decode(text.split_whitespace().collect::<String>()).ok())
+    {
+        Some(ciphertext) if !ciphertext.is_empty() => ciphertext,
+        _ => content,
+    }
 }
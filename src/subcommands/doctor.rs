@@ -0,0 +1,449 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checks the health of the Parsec service from end to end.
+//!
+//! After checking the core operations, each provider is probed with throwaway keys: random
+//! generation, ECDSA and RSA signatures, RSA encryption and ECDH key agreement. Probes needing an
+//! operation that the provider does not support are skipped. The keys are volatile and deleted
+//! whatever the outcome of the probe.
+
+use crate::cli::OutputFormat;
+use crate::envelope::{ecies_content_key, ecies_encapsulate};
+use crate::error::{Error, Result, ToolErrorKind};
+use crate::util::{hash_data, TemporaryKey};
+use clap::Parser;
+use log::{error, info};
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricEncryption, AsymmetricSignature, Hash, KeyAgreement, RawKeyAgreement,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{
+    Attributes, EccFamily, Lifetime, Policy, Type, UsageFlags,
+};
+use parsec_client::core::interface::requests::{Opcode, ProviderId};
+use parsec_client::BasicClient;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::process;
+
+const MESSAGE: &[u8] = b"Parsec doctor";
+
+/// Checks the health of the Parsec service from end to end.
+#[derive(Debug, Parser)]
+pub struct Doctor {
+    /// Size in bits of the RSA keys created by the probes.
+    #[structopt(long = "rsa-key-size", default_value = "2048")]
+    rsa_key_size: usize,
+}
+
+/// Outcome of a check.
+#[derive(Debug)]
+enum Outcome {
+    Pass,
+    /// The provider does not support the operation
+    Skip(Opcode),
+    Fail(Error),
+}
+
+impl Outcome {
+    fn text(&self) -> String {
+        match self {
+            Outcome::Pass => String::from("pass"),
+            Outcome::Skip(opcode) => format!("skip (no {:?})", opcode),
+            Outcome::Fail(e) => format!("FAIL ({})", e.status_or_message()),
+        }
+    }
+
+    fn json(&self) -> Value {
+        match self {
+            Outcome::Pass => json!({ "result": "pass" }),
+            Outcome::Skip(opcode) => {
+                json!({ "result": "skip", "missing_opcode": format!("{:?}", opcode) })
+            }
+            Outcome::Fail(e) => json!({ "result": "fail", "status": e.status_or_message() }),
+        }
+    }
+}
+
+impl From<Result<()>> for Outcome {
+    fn from(result: Result<()>) -> Self {
+        match result {
+            Ok(()) => Outcome::Pass,
+            Err(e) => Outcome::Fail(e),
+        }
+    }
+}
+
+/// Checks of a provider.
+#[derive(Debug)]
+struct ProviderReport {
+    provider: ProviderId,
+    checks: Vec<(&'static str, Outcome)>,
+}
+
+impl Doctor {
+    /// Runs the checks and prints the results. Only the given provider is checked, if any.
+    pub fn run(
+        &self,
+        mut basic_client: BasicClient,
+        format: OutputFormat,
+        provider: Option<ProviderId>,
+    ) -> Result<()> {
+        let mut service_checks = Vec::new();
+        let mut providers = Vec::new();
+
+        info!("Checking the core operations...");
+        service_checks.push((
+            "ping",
+            Outcome::from(basic_client.ping().map(|_| ()).map_err(Error::from)),
+        ));
+        service_checks.push((
+            "list-authenticators",
+            Outcome::from(
+                basic_client
+                    .list_authenticators()
+                    .map(|_| ())
+                    .map_err(Error::from),
+            ),
+        ));
+        service_checks.push((
+            "list-keys",
+            Outcome::from(basic_client.list_keys().map(|_| ()).map_err(Error::from)),
+        ));
+        match basic_client.list_providers() {
+            Ok(infos) => {
+                service_checks.push(("list-providers", Outcome::Pass));
+                let wanted = provider;
+                providers = infos
+                    .into_iter()
+                    .map(|info| info.id)
+                    .filter(|id| *id != ProviderId::Core && wanted.map_or(true, |w| w == *id))
+                    .collect();
+                if let Some(wanted) = wanted {
+                    if !providers.contains(&wanted) {
+                        return Err(ToolErrorKind::UnsupportedOperation(format!(
+                            "the {} is not available in the service",
+                            wanted
+                        ))
+                        .into());
+                    }
+                }
+            }
+            Err(e) => service_checks.push(("list-providers", Outcome::Fail(e.into()))),
+        }
+
+        let mut reports = Vec::new();
+        for provider in providers {
+            info!("Checking the {}...", provider);
+            basic_client.set_implicit_provider(provider);
+            reports.push(self.check_provider(&basic_client, provider));
+        }
+
+        match format {
+            OutputFormat::Text => print_text(&service_checks, &reports),
+            OutputFormat::Json => print_json(&service_checks, &reports),
+        }
+
+        let failure = service_checks
+            .into_iter()
+            .chain(reports.into_iter().flat_map(|report| report.checks))
+            .find_map(|(_, outcome)| match outcome {
+                Outcome::Fail(e) => Some(e),
+                _ => None,
+            });
+        match failure {
+            None => Ok(()),
+            Some(e) => {
+                error!("Some checks failed");
+                Err(e)
+            }
+        }
+    }
+
+    fn check_provider(&self, basic_client: &BasicClient, provider: ProviderId) -> ProviderReport {
+        let opcodes = match basic_client.list_opcodes(provider) {
+            Ok(opcodes) => opcodes,
+            Err(e) => {
+                return ProviderReport {
+                    provider,
+                    checks: vec![("list-opcodes", Outcome::Fail(e.into()))],
+                }
+            }
+        };
+
+        let mut checks = vec![("list-opcodes", Outcome::Pass)];
+        checks.push((
+            "generate-random",
+            probe(&opcodes, &[Opcode::PsaGenerateRandom], || {
+                let random = basic_client.psa_generate_random(32)?;
+                expect(
+                    random.len() == 32,
+                    "the random bytes do not have the requested length",
+                )
+            }),
+        ));
+        checks.push((
+            "ecdsa-sign-verify",
+            probe(&opcodes, &SIGN_OPCODES, || {
+                let alg = AsymmetricSignature::Ecdsa {
+                    hash_alg: Hash::Sha256.into(),
+                };
+                with_key(basic_client, "ecdsa", ecc_attributes(alg.into()), |key| {
+                    sign_verify(basic_client, key, alg)
+                })
+            }),
+        ));
+        checks.push((
+            "rsa-sign-verify",
+            probe(&opcodes, &SIGN_OPCODES, || {
+                let alg = AsymmetricSignature::RsaPkcs1v15Sign {
+                    hash_alg: Hash::Sha256.into(),
+                };
+                let attributes = self.rsa_attributes(alg.into());
+                with_key(basic_client, "rsa-sign", attributes, |key| {
+                    sign_verify(basic_client, key, alg)
+                })
+            }),
+        ));
+        for (name, alg) in [
+            (
+                "rsa-pkcs1v15-encrypt-decrypt",
+                AsymmetricEncryption::RsaPkcs1v15Crypt,
+            ),
+            (
+                "rsa-oaep-encrypt-decrypt",
+                AsymmetricEncryption::RsaOaep {
+                    hash_alg: Hash::Sha256,
+                },
+            ),
+        ] {
+            checks.push((
+                name,
+                probe(&opcodes, &ENCRYPT_OPCODES, || {
+                    let attributes = self.rsa_attributes(alg.into());
+                    with_key(basic_client, "rsa-encrypt", attributes, |key| {
+                        let ciphertext =
+                            basic_client.psa_asymmetric_encrypt(key, alg, MESSAGE, None)?;
+                        let plaintext =
+                            basic_client.psa_asymmetric_decrypt(key, alg, &ciphertext, None)?;
+                        expect(plaintext == MESSAGE, "the decrypted data differs")
+                    })
+                }),
+            ));
+        }
+        checks.push((
+            "ecdh-key-agreement",
+            probe(&opcodes, &KEY_AGREEMENT_OPCODES, || {
+                let alg = KeyAgreement::Raw(RawKeyAgreement::Ecdh);
+                with_key(basic_client, "ecdh", ecc_attributes(alg.into()), |key| {
+                    let public_key = basic_client.psa_export_public_key(key)?;
+                    let (ephemeral_public_key, content_key) = ecies_encapsulate(&public_key, 256)?;
+                    let shared_secret = basic_client.psa_raw_key_agreement(
+                        RawKeyAgreement::Ecdh,
+                        key,
+                        &ephemeral_public_key,
+                    )?;
+                    let parsec_content_key =
+                        ecies_content_key(&shared_secret, &ephemeral_public_key, &public_key)?;
+                    expect(
+                        *parsec_content_key == *content_key,
+                        "the shared secrets differ",
+                    )
+                })
+            }),
+        ));
+
+        ProviderReport { provider, checks }
+    }
+
+    fn rsa_attributes(&self, permitted_algorithms: Algorithm) -> Attributes {
+        Attributes {
+            lifetime: Lifetime::Volatile,
+            key_type: Type::RsaKeyPair,
+            bits: self.rsa_key_size,
+            policy: Policy {
+                usage_flags: usage_flags(),
+                permitted_algorithms,
+            },
+        }
+    }
+}
+
+const SIGN_OPCODES: [Opcode; 4] = [
+    Opcode::PsaGenerateKey,
+    Opcode::PsaSignHash,
+    Opcode::PsaVerifyHash,
+    Opcode::PsaDestroyKey,
+];
+const ENCRYPT_OPCODES: [Opcode; 4] = [
+    Opcode::PsaGenerateKey,
+    Opcode::PsaAsymmetricEncrypt,
+    Opcode::PsaAsymmetricDecrypt,
+    Opcode::PsaDestroyKey,
+];
+const KEY_AGREEMENT_OPCODES: [Opcode; 4] = [
+    Opcode::PsaGenerateKey,
+    Opcode::PsaExportPublicKey,
+    Opcode::PsaRawKeyAgreement,
+    Opcode::PsaDestroyKey,
+];
+
+/// Runs the probe if the provider supports all the operations it needs.
+fn probe(
+    opcodes: &HashSet<Opcode>,
+    needed: &[Opcode],
+    probe: impl FnOnce() -> Result<()>,
+) -> Outcome {
+    match needed.iter().find(|opcode| !opcodes.contains(opcode)) {
+        Some(opcode) => Outcome::Skip(*opcode),
+        None => probe().into(),
+    }
+}
+
+/// Creates a throwaway key and runs the probe with it. The key is deleted when the probe returns.
+fn with_key(
+    basic_client: &BasicClient,
+    purpose: &str,
+    attributes: Attributes,
+    probe: impl FnOnce(&str) -> Result<()>,
+) -> Result<()> {
+    let key_name = format!("parsec-tool-doctor-{}-{}", process::id(), purpose);
+    let key = TemporaryKey::generate(basic_client, key_name, attributes)?;
+    probe(key.name())
+}
+
+fn sign_verify(basic_client: &BasicClient, key: &str, alg: AsymmetricSignature) -> Result<()> {
+    let hash = hash_data(MESSAGE, Hash::Sha256)?;
+    let signature = basic_client.psa_sign_hash(key, &hash, alg)?;
+    basic_client.psa_verify_hash(key, &hash, alg, &signature)?;
+    Ok(())
+}
+
+fn ecc_attributes(permitted_algorithms: Algorithm) -> Attributes {
+    Attributes {
+        lifetime: Lifetime::Volatile,
+        key_type: Type::EccKeyPair {
+            curve_family: EccFamily::SecpR1,
+        },
+        bits: 256,
+        policy: Policy {
+            usage_flags: usage_flags(),
+            permitted_algorithms,
+        },
+    }
+}
+
+/// Usage flags of the throwaway keys, each probe using a subset of them.
+fn usage_flags() -> UsageFlags {
+    let mut usage_flags = UsageFlags::default();
+    let _ = usage_flags
+        .set_sign_hash()
+        .set_verify_hash()
+        .set_encrypt()
+        .set_decrypt()
+        .set_export()
+        .set_derive();
+    usage_flags
+}
+
+fn expect(condition: bool, message: &str) -> Result<()> {
+    if condition {
+        Ok(())
+    } else {
+        Err(ToolErrorKind::InvalidData(format!("check failed: {}", message)).into())
+    }
+}
+
+fn print_text(service_checks: &[(&'static str, Outcome)], reports: &[ProviderReport]) {
+    let width = service_checks
+        .iter()
+        .chain(reports.iter().flat_map(|report| report.checks.iter()))
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        + 2;
+
+    println!("{:width$}Service", "", width = width);
+    for (name, outcome) in service_checks {
+        println!("{:width$}{}", name, outcome.text(), width = width);
+    }
+
+    if reports.is_empty() {
+        return;
+    }
+    let headers: Vec<String> = reports
+        .iter()
+        .map(|report| format!("0x{:02x} ({})", report.provider as u32, report.provider))
+        .collect();
+    let columns: Vec<usize> = reports
+        .iter()
+        .zip(&headers)
+        .map(|(report, header)| {
+            report
+                .checks
+                .iter()
+                .map(|(_, outcome)| outcome.text().len())
+                .chain(Some(header.len()))
+                .max()
+                .unwrap_or(0)
+                + 2
+        })
+        .collect();
+
+    println!();
+    print!("{:width$}", "", width = width);
+    for (header, column) in headers.iter().zip(&columns) {
+        print!("{:column$}", header, column = column);
+    }
+    println!();
+    // All providers run the same checks, except if listing their opcodes failed.
+    let names: Vec<&str> = reports
+        .iter()
+        .max_by_key(|report| report.checks.len())
+        .map(|report| report.checks.iter().map(|(name, _)| *name).collect())
+        .unwrap_or_default();
+    for name in names {
+        print!("{:width$}", name, width = width);
+        for (report, column) in reports.iter().zip(&columns) {
+            let cell = report
+                .checks
+                .iter()
+                .find(|(check, _)| *check == name)
+                .map_or_else(|| String::from("-"), |(_, outcome)| outcome.text());
+            print!("{:column$}", cell, column = column);
+        }
+        println!();
+    }
+}
+
+fn print_json(service_checks: &[(&'static str, Outcome)], reports: &[ProviderReport]) {
+    let checks_json = |checks: &[(&'static str, Outcome)]| -> Value {
+        checks
+            .iter()
+            .map(|(name, outcome)| {
+                let mut check = outcome.json();
+                let _ = check
+                    .as_object_mut()
+                    .map(|check| check.insert(String::from("check"), json!(name)));
+                check
+            })
+            .collect()
+    };
+    let providers: Vec<Value> = reports
+        .iter()
+        .map(|report| {
+            json!({
+                "id": report.provider as u8,
+                "name": report.provider.to_string(),
+                "checks": checks_json(&report.checks),
+            })
+        })
+        .collect();
+    println!(
+        "{}",
+        json!({
+            "service": checks_json(service_checks),
+            "providers": providers,
+        })
+    );
+}
@@ -9,63 +9,358 @@
 //! used). It is not possible to encrypt data using the private part of an asymmetric
 //! key pair. Encryption with symmetric keys will be added in the future.
 //!
-//! No salt is used.
+//! RSA-OAEP encryption can use a label (`--label`, `--label-hex` or `--label-file`), which must be
+//! given again for decryption. No label is used by default.
 //!
-//! The input is a plain text message string, which is treated as raw bytes.
+//! The input is a plain text message string, which is treated as raw bytes, or the content of a
+//! file.
 //!
-//! The output is base64-encoded ciphertext.
+//! The output is base64-encoded ciphertext. With `--envelope`, the input is encrypted locally with
+//! AES-256-GCM under a random content key, which is itself encrypted with the RSA-OAEP key: this
+//! lifts the size limit of RSA encryption. The output is then the binary envelope container.
+//!
+//! ECC keys on the NIST P-256 and P-384 curves allowing ECDH are used with ECIES: the content key
+//! of the envelope is derived from an ECDH exchange between an ephemeral key and the Parsec key.
+//! ECIES only makes envelopes, so `--envelope` must be given.
+//!
+//! With `--pubkey`, the data is encrypted to a public key read from a file instead of a
+//! Parsec key. ECIES encryption is made locally, without the Parsec service, while RSA public keys
+//! are imported in Parsec as volatile keys for the time of the encryption.
 
+use crate::envelope::{
+    ecies_encapsulate, local_nonce, Envelope, KeyWrapping, CONTENT_KEY_LEN, CONTENT_NONCE_LEN,
+};
 use crate::error::{Result, ToolErrorKind};
+use crate::util::{check_label, read_der, read_input, read_label, write_binary, HashAlgorithm};
 use clap::Parser;
-use log::{error, info};
-use parsec_client::core::interface::operations::psa_algorithm::Algorithm;
+use log::{info, warn};
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricEncryption, Hash, KeyAgreement, RawKeyAgreement,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{
+    Attributes, EccFamily, Lifetime, Policy, Type, UsageFlags,
+};
 use parsec_client::BasicClient;
+use picky_asn1_x509::{
+    oids, AlgorithmIdentifierParameters, PublicKey, RsaPublicKey, SubjectPublicKeyInfo,
+};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
 
 /// Encrypts data.
 #[derive(Debug, Parser)]
 pub struct Encrypt {
-    #[structopt(short = 'k', long = "key-name")]
-    key_name: String,
+    #[structopt(short = 'k', long = "key-name", required_unless_present = "public_key")]
+    key_name: Option<String>,
+
+    /// File containing the public key (PEM or DER) to encrypt to, instead of a Parsec key
+    #[structopt(long = "pubkey", conflicts_with = "key_name")]
+    public_key: Option<PathBuf>,
+
+    /// Encrypt to an RSA public key with RSA OAEP instead of RSA PKCS#1 v1.5
+    #[structopt(short = 'o', long = "oaep", requires = "public_key")]
+    oaep: bool,
+
+    /// Hash algorithm of RSA OAEP encryption to an RSA public key (SHA-256 by default)
+    #[structopt(long = "oaep-hash", requires = "oaep")]
+    oaep_hash: Option<HashAlgorithm>,
 
     /// Plaintext input string.
-    input_data: String,
+    #[structopt(required_unless_present = "input_file")]
+    input_data: Option<String>,
+
+    /// File containing the plaintext, instead of a string
+    #[structopt(short = 'i', long = "input-file", conflicts_with = "input_data")]
+    input_file: Option<PathBuf>,
+
+    /// Encrypt the input with a random AES-256-GCM key, wrapped with the RSA-OAEP key or derived
+    /// with ECIES, and output the binary envelope. Needed for ECC keys.
+    #[structopt(short = 'e', long = "envelope")]
+    envelope: bool,
+
+    /// Label of RSA-OAEP encryption, as a string
+    #[structopt(short = 'l', long = "label")]
+    label: Option<String>,
+
+    /// Label of RSA-OAEP encryption, in hexadecimal
+    #[structopt(long = "label-hex", conflicts_with = "label")]
+    label_hex: Option<String>,
+
+    /// File containing the label of RSA-OAEP encryption
+    #[structopt(long = "label-file", conflicts_with_all = ["label", "label_hex"])]
+    label_file: Option<PathBuf>,
 }
 
 impl Encrypt {
     /// Encrypts data.
     pub fn run(&self, basic_client: BasicClient) -> Result<()> {
-        let input = self.input_data.as_bytes();
+        let input = read_input(&self.input_data, &self.input_file)?;
+        let label = read_label(&self.label, &self.label_hex, &self.label_file)?;
 
-        let alg = basic_client
-            .key_attributes(&self.key_name)?
-            .policy
-            .permitted_algorithms;
+        let key_name = match (&self.key_name, &self.public_key) {
+            (Some(key_name), None) => key_name,
+            (None, Some(public_key)) => {
+                return self.encrypt_to_public_key(&basic_client, public_key, label, &input);
+            }
+            _ => {
+                return Err(ToolErrorKind::MissingInput(
+                    "either a key name or a public key must be given".to_string(),
+                )
+                .into());
+            }
+        };
+        let attributes = basic_client.key_attributes(key_name)?;
+        let alg = attributes.policy.permitted_algorithms;
+        check_label(key_name, alg, &label)?;
 
-        let ciphertext = match alg {
+        match alg {
+            Algorithm::KeyAgreement(KeyAgreement::Raw(RawKeyAgreement::Ecdh)) => {
+                match attributes.key_type {
+                    Type::EccKeyPair {
+                        curve_family: EccFamily::SecpR1,
+                    }
+                    | Type::EccPublicKey {
+                        curve_family: EccFamily::SecpR1,
+                    } => (),
+                    // ECIES is only supported on the NIST curves.
+                    other => {
+                        return Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+                            key_name: key_name.clone(),
+                            algorithm: format!("{:?}", other),
+                            operation: "ECIES encryption".to_string(),
+                        }
+                        .into());
+                    }
+                }
+                self.check_ecies_envelope()?;
+                let public_key = basic_client.psa_export_public_key(key_name)?;
+                encrypt_ecies(&public_key, attributes.bits, &input)
+            }
             Algorithm::AsymmetricEncryption(alg) => {
-                info!("Encrypting data with {:?}...", alg);
-                basic_client.psa_asymmetric_encrypt(&self.key_name, alg, input, None)?
+                self.encrypt_rsa(&basic_client, key_name, alg, label.as_deref(), &input)
             }
             Algorithm::Cipher(_) | Algorithm::Aead(_) => {
-                error!(
-                    "Key's algorithm is {:?} which is not currently supported for encryption.",
-                    alg
-                );
-                return Err(ToolErrorKind::NotSupported.into());
+                Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+                    key_name: key_name.clone(),
+                    algorithm: format!("{:?}", alg),
+                    operation: "encryption".to_string(),
+                }
+                .into())
             }
-            other => {
-                error!(
-                    "Key's algorithm is {:?} which cannot be used for encryption.",
-                    other
-                );
-                return Err(ToolErrorKind::WrongKeyAlgorithm.into());
+            other => Err(ToolErrorKind::KeyAlgorithmMismatch {
+                key_name: key_name.clone(),
+                expected: "an asymmetric encryption algorithm".to_string(),
+                actual: format!("{:?}", other),
             }
-        };
+            .into()),
+        }
+    }
+
+    /// Encrypts with an RSA key, in an envelope if asked to.
+    fn encrypt_rsa(
+        &self,
+        basic_client: &BasicClient,
+        key_name: &str,
+        alg: AsymmetricEncryption,
+        label: Option<&[u8]>,
+        input: &[u8],
+    ) -> Result<()> {
+        if !self.envelope {
+            info!("Encrypting data with {:?}...", alg);
+            let ciphertext = basic_client.psa_asymmetric_encrypt(key_name, alg, input, label)?;
+
+            let ciphertext = base64::encode(ciphertext);
+
+            println!("{}", ciphertext);
+
+            return Ok(());
+        }
+
+        if !matches!(alg, AsymmetricEncryption::RsaOaep { .. }) {
+            // Envelopes are only made with RSA-OAEP keys.
+            return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                key_name: key_name.to_string(),
+                expected: format!("{:?}", KeyWrapping::RsaOaep),
+                actual: format!("{:?}", alg),
+            }
+            .into());
+        }
+
+        info!("Generating the content key...");
+        let content_key = Zeroizing::new(basic_client.psa_generate_random(CONTENT_KEY_LEN)?);
+        info!("Wrapping the content key with {:?}...", alg);
+        let wrapped_key =
+            basic_client.psa_asymmetric_encrypt(key_name, alg, &content_key, label)?;
+
+        let nonce = basic_client.psa_generate_random(CONTENT_NONCE_LEN)?;
 
-        let ciphertext = base64::encode(ciphertext);
+        write_envelope(
+            KeyWrapping::RsaOaep,
+            wrapped_key,
+            &content_key,
+            &nonce,
+            input,
+        )
+    }
 
-        println!("{}", ciphertext);
+    /// ECIES always makes an envelope, check that it was asked for.
+    fn check_ecies_envelope(&self) -> Result<()> {
+        if self.envelope {
+            Ok(())
+        } else {
+            Err(ToolErrorKind::MissingInput(
+                "ECIES encryption outputs an envelope, --envelope must be given".to_string(),
+            )
+            .into())
+        }
+    }
 
-        Ok(())
+    /// Indicates if the Parsec service is needed to run this command: ECIES encryption to a
+    /// public key is made locally.
+    pub fn needs_service(&self) -> bool {
+        match &self.public_key {
+            Some(public_key) => !matches!(
+                read_public_key(public_key).map(|spki| spki.subject_public_key),
+                Ok(PublicKey::Ec(_))
+            ),
+            None => true,
+        }
     }
+
+    fn encrypt_to_public_key(
+        &self,
+        basic_client: &BasicClient,
+        public_key: &Path,
+        label: Option<Vec<u8>>,
+        input: &[u8],
+    ) -> Result<()> {
+        let public_key_file = public_key.display().to_string();
+        let spki_public_key = read_public_key(public_key)?;
+
+        match &spki_public_key.subject_public_key {
+            PublicKey::Rsa(rsa) => {
+                let alg = if self.oaep {
+                    AsymmetricEncryption::RsaOaep {
+                        hash_alg: self.oaep_hash.map_or(Hash::Sha256, Hash::from),
+                    }
+                } else {
+                    AsymmetricEncryption::RsaPkcs1v15Crypt
+                };
+                check_label(&public_key_file, alg.into(), &label)?;
+                let rsa_public_key = picky_asn1_der::to_vec(&rsa.0).map_err(|_| {
+                    ToolErrorKind::InvalidData("could not serialise the RSA public key".to_string())
+                })?;
+                let attributes = Attributes {
+                    lifetime: Lifetime::Volatile,
+                    key_type: Type::RsaPublicKey,
+                    bits: rsa.0.modulus.as_unsigned_bytes_be().len() * 8,
+                    policy: Policy {
+                        usage_flags: {
+                            let mut usage_flags = UsageFlags::default();
+                            let _ = usage_flags.set_encrypt();
+                            usage_flags
+                        },
+                        permitted_algorithms: alg.into(),
+                    },
+                };
+
+                // Volatile keys are still named, the name only has to be unique for the time of
+                // the encryption.
+                let key_name = format!(
+                    "parsec-tool-public-key-{}-{}",
+                    process::id(),
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |time| time.as_nanos())
+                );
+                info!(
+                    "Importing the public key as volatile key \"{}\"...",
+                    key_name
+                );
+                basic_client.psa_import_key(&key_name, &rsa_public_key, attributes)?;
+                let result =
+                    self.encrypt_rsa(basic_client, &key_name, alg, label.as_deref(), input);
+                if let Err(e) = basic_client.psa_destroy_key(&key_name) {
+                    warn!("Could not destroy volatile key \"{}\": {}", key_name, e);
+                }
+                result
+            }
+            PublicKey::Ec(point) => {
+                if label.is_some() {
+                    return Err(ToolErrorKind::UnsupportedOperation(
+                        "a label can only be used with RSA-OAEP".to_string(),
+                    )
+                    .into());
+                }
+                let curve: String = match spki_public_key.algorithm.parameters() {
+                    AlgorithmIdentifierParameters::Ec(params) => params.curve_oid().into(),
+                    _ => String::new(),
+                };
+                let bits = match curve.as_str() {
+                    oids::SECP256R1 => 256,
+                    oids::SECP384R1 => 384,
+                    _ => {
+                        return Err(ToolErrorKind::UnsupportedOperation(
+                            "ECIES is only supported on the P-256 and P-384 curves".to_string(),
+                        )
+                        .into());
+                    }
+                };
+                self.check_ecies_envelope()?;
+                encrypt_ecies(point.0.payload_view(), bits, input)
+            }
+            PublicKey::Ed(_) => Err(ToolErrorKind::UnsupportedOperation(
+                "Edwards curve public keys are not supported for encryption".to_string(),
+            )
+            .into()),
+        }
+    }
+}
+
+// Reads a public key file, in the SubjectPublicKeyInfo format or, for RSA keys, in the PKCS#1
+// format ("RSA PUBLIC KEY" PEM blocks).
+fn read_public_key(public_key: &Path) -> Result<SubjectPublicKeyInfo> {
+    let public_key = read_der(public_key)?;
+    if let Ok(spki) = picky_asn1_der::from_bytes::<SubjectPublicKeyInfo>(&public_key) {
+        return Ok(spki);
+    }
+    match picky_asn1_der::from_bytes::<RsaPublicKey>(&public_key) {
+        Ok(rsa) => Ok(SubjectPublicKeyInfo::new_rsa_key(
+            rsa.modulus,
+            rsa.public_exponent,
+        )),
+        Err(_) => {
+            Err(ToolErrorKind::InvalidData("could not parse the public key".to_string()).into())
+        }
+    }
+}
+
+/// Encrypts with ECIES to a public key on a NIST curve. Everything is made locally, the ephemeral
+/// key and the nonce come from the system random generator.
+fn encrypt_ecies(public_key: &[u8], bits: usize, input: &[u8]) -> Result<()> {
+    info!("Deriving the content key with an ephemeral ECDH key...");
+    let (ephemeral_public_key, content_key) = ecies_encapsulate(public_key, bits)?;
+
+    write_envelope(
+        KeyWrapping::Ecies,
+        ephemeral_public_key,
+        &content_key,
+        &local_nonce()?,
+        input,
+    )
+}
+
+/// Encrypts the input with the content key and writes the envelope.
+fn write_envelope(
+    key_wrapping: KeyWrapping,
+    wrapped_key: Vec<u8>,
+    content_key: &[u8],
+    nonce: &[u8],
+    input: &[u8],
+) -> Result<()> {
+    let envelope = Envelope::seal(key_wrapping, wrapped_key, content_key, nonce, input)?;
+
+    write_binary(&envelope.encode())
 }
@@ -3,55 +3,97 @@
 
 //! Exports a public key.
 
-use crate::error::{Result, ToolErrorKind};
-use clap::Parser;
-use log::error;
+use crate::error::{Error, Result, ToolErrorKind};
+use crate::ssh;
+use crate::util::{base64_url, print_pem, write_binary};
+use clap::{Parser, ValueEnum};
 use oid::prelude::*;
-use parsec_client::core::interface::operations::psa_key_attributes::{EccFamily, Type};
+use parsec_client::core::interface::operations::psa_key_attributes::{Attributes, EccFamily, Type};
 use parsec_client::BasicClient;
 use picky_asn1::bit_string::BitString;
 use picky_asn1_x509::{
     AlgorithmIdentifier, EcParameters, PublicKey, RsaPublicKey, SubjectPublicKeyInfo,
 };
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
 
-/// Exports a PEM-encoded public key.
+/// Exports a public key, PEM-encoded by default.
 #[derive(Debug, Parser)]
 pub struct ExportPublicKey {
     #[structopt(short = 'k', long = "key-name")]
     key_name: String,
 
-    /// Export RSA Public Key in PKCS#1 format.
-    #[structopt(long = "pkcs1")]
+    /// Export RSA Public Key in PKCS#1 format. Same as "--format pkcs1".
+    #[structopt(long = "pkcs1", conflicts_with = "format")]
     pkcs1: bool,
+
+    /// The format of the exported public key.
+    #[structopt(short = 'f', long = "format", default_value = "pem")]
+    format: PublicKeyFormat,
+}
+
+/// Formats in which a public key can be exported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum PublicKeyFormat {
+    /// SubjectPublicKeyInfo, PEM-encoded.
+    Pem,
+    /// SubjectPublicKeyInfo, DER-encoded (binary output).
+    Der,
+    /// PKCS#1 RSAPublicKey, PEM-encoded (RSA keys only).
+    Pkcs1,
+    /// OpenSSH authorized_keys line.
+    Openssh,
+    /// JSON Web Key (RFC 7517), with a RFC 7638 thumbprint as "kid".
+    Jwk,
+    /// Raw public key as exported by Parsec: the modulus for RSA keys, the uncompressed point for
+    /// ECC keys (binary output).
+    Raw,
 }
 
 impl ExportPublicKey {
     /// Exports a public key.
     pub fn run(&self, basic_client: BasicClient) -> Result<()> {
-        let mut tag = String::from("PUBLIC KEY");
-        let mut psa_public_key = basic_client.psa_export_public_key(&self.key_name)?;
+        let format = if self.pkcs1 {
+            PublicKeyFormat::Pkcs1
+        } else {
+            self.format
+        };
+        let psa_public_key = basic_client.psa_export_public_key(&self.key_name)?;
         let psa_key_attributes = basic_client.key_attributes(&self.key_name)?;
 
         match psa_key_attributes.key_type {
             Type::RsaKeyPair | Type::RsaPublicKey => {
-                if self.pkcs1 {
-                    tag = String::from("RSA PUBLIC KEY");
-                } else {
-                    psa_public_key = picky_asn1_der::to_vec(&SubjectPublicKeyInfo {
-                        algorithm: AlgorithmIdentifier::new_rsa_encryption(),
-                        subject_public_key: PublicKey::Rsa(
-                            picky_asn1_der::from_bytes::<RsaPublicKey>(&psa_public_key)
-                                .map_err(|_| {
-                                    error!("Could not deserialise RSA key");
-                                    ToolErrorKind::IncorrectData
-                                })?
-                                .into(),
-                        ),
-                    })
+                let rsa_public_key = picky_asn1_der::from_bytes::<RsaPublicKey>(&psa_public_key)
                     .map_err(|_| {
-                        error!("Could not serialise RSA key");
-                        ToolErrorKind::IncorrectData
+                        ToolErrorKind::InvalidData("could not deserialise the RSA key".to_string())
                     })?;
+                match format {
+                    PublicKeyFormat::Pkcs1 => print_pem("RSA PUBLIC KEY", psa_public_key),
+                    PublicKeyFormat::Pem | PublicKeyFormat::Der => {
+                        let spki = picky_asn1_der::to_vec(&SubjectPublicKeyInfo {
+                            algorithm: AlgorithmIdentifier::new_rsa_encryption(),
+                            subject_public_key: PublicKey::Rsa(rsa_public_key.into()),
+                        })
+                        .map_err(|_| {
+                            ToolErrorKind::InvalidData(
+                                "could not serialise the RSA key".to_string(),
+                            )
+                        })?;
+                        print_spki(format, spki)?;
+                    }
+                    PublicKeyFormat::Openssh => {
+                        print_openssh(&psa_key_attributes, &psa_public_key, &self.key_name)?
+                    }
+                    PublicKeyFormat::Jwk => {
+                        let e = base64_url(rsa_public_key.public_exponent.as_unsigned_bytes_be());
+                        let n = base64_url(rsa_public_key.modulus.as_unsigned_bytes_be());
+                        // Required members only, in lexicographic order (RFC 7638, section 3.2).
+                        let thumbprint_input = json!({ "e": e, "kty": "RSA", "n": n });
+                        print_jwk(thumbprint_input)?;
+                    }
+                    PublicKeyFormat::Raw => {
+                        write_binary(rsa_public_key.modulus.as_unsigned_bytes_be())?
+                    }
                 }
             }
             Type::EccKeyPair {
@@ -59,15 +101,21 @@ impl ExportPublicKey {
             }
             | Type::EccPublicKey {
                 curve_family: curve,
-            } => {
-                if self.pkcs1 {
-                    error!("PKCS1 format doesn't support ECC keys");
-                    return Err(ToolErrorKind::WrongKeyAlgorithm.into());
-                } else {
-                    psa_public_key = picky_asn1_der::to_vec(&SubjectPublicKeyInfo {
+            } => match format {
+                // PKCS1 format doesn't support ECC keys.
+                PublicKeyFormat::Pkcs1 => {
+                    return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                        key_name: self.key_name.clone(),
+                        expected: "an RSA key".to_string(),
+                        actual: format!("{:?}", psa_key_attributes.key_type),
+                    }
+                    .into());
+                }
+                PublicKeyFormat::Pem | PublicKeyFormat::Der => {
+                    let spki = picky_asn1_der::to_vec(&SubjectPublicKeyInfo {
                         algorithm: AlgorithmIdentifier::new_elliptic_curve(
                             EcParameters::NamedCurve(
-                                curve_oid(curve, psa_key_attributes.bits)?.into(),
+                                curve_oid(&self.key_name, curve, psa_key_attributes.bits)?.into(),
                             ),
                         ),
                         subject_public_key: PublicKey::Ec(
@@ -75,33 +123,95 @@ impl ExportPublicKey {
                         ),
                     })
                     .map_err(|_| {
-                        error!("Could not serialise ECC key");
-                        ToolErrorKind::IncorrectData
+                        ToolErrorKind::InvalidData("could not serialise the ECC key".to_string())
                     })?;
+                    print_spki(format, spki)?;
                 }
-            }
-            _ => {
-                error!("Unsupported type of key");
-                return Err(ToolErrorKind::NotSupported.into());
+                PublicKeyFormat::Openssh => {
+                    print_openssh(&psa_key_attributes, &psa_public_key, &self.key_name)?
+                }
+                PublicKeyFormat::Jwk => {
+                    let crv = jwk_curve_name(&self.key_name, curve, psa_key_attributes.bits)?;
+                    // The PSA format of ECC public keys is the uncompressed point: 0x04 || x || y.
+                    if psa_public_key.first() != Some(&0x04) {
+                        return Err(ToolErrorKind::InvalidData(
+                            "only uncompressed ECC points are supported".to_string(),
+                        )
+                        .into());
+                    }
+                    let (x, y) = psa_public_key[1..].split_at((psa_public_key.len() - 1) / 2);
+                    // Required members only, in lexicographic order (RFC 7638, section 3.2).
+                    let thumbprint_input = json!({
+                        "crv": crv,
+                        "kty": "EC",
+                        "x": base64_url(x),
+                        "y": base64_url(y),
+                    });
+                    print_jwk(thumbprint_input)?;
+                }
+                PublicKeyFormat::Raw => write_binary(&psa_public_key)?,
+            },
+            other => {
+                return Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+                    key_name: self.key_name.clone(),
+                    algorithm: format!("{:?}", other),
+                    operation: "public key export".to_string(),
+                }
+                .into());
             }
         };
 
-        let pem_encoded = pem::encode_config(
-            &pem::Pem {
-                tag,
-                contents: psa_public_key,
-            },
-            pem::EncodeConfig {
-                line_ending: pem::LineEnding::LF,
-            },
-        );
+        Ok(())
+    }
+}
 
-        print!("{}", pem_encoded);
+fn print_spki(format: PublicKeyFormat, spki: Vec<u8>) -> Result<()> {
+    if format == PublicKeyFormat::Der {
+        write_binary(&spki)
+    } else {
+        print_pem("PUBLIC KEY", spki);
         Ok(())
     }
 }
 
-fn curve_oid(curve: EccFamily, key_bits: usize) -> Result<ObjectIdentifier> {
+fn print_openssh(attributes: &Attributes, psa_public_key: &[u8], key_name: &str) -> Result<()> {
+    let (key_type, blob) = ssh::public_key_blob(key_name, attributes, psa_public_key)?;
+    println!("{} {} {}", key_type, base64::encode(blob), key_name);
+    Ok(())
+}
+
+// Prints the JWK made of the required members given, with a RFC 7638 thumbprint added as "kid".
+fn print_jwk(mut jwk: Value) -> Result<()> {
+    // serde_json sorts the members and does not add any whitespace, which is the canonical form
+    // needed to compute the thumbprint.
+    let canonical = serde_json::to_string(&jwk)
+        .map_err(|_| ToolErrorKind::InvalidData("could not serialise the JWK".to_string()))?;
+    jwk["kid"] = Value::String(base64_url(&Sha256::digest(canonical.as_bytes())));
+    let jwk = serde_json::to_string_pretty(&jwk)
+        .map_err(|_| ToolErrorKind::InvalidData("could not serialise the JWK".to_string()))?;
+    println!("{}", jwk);
+    Ok(())
+}
+
+// Curve names as registered for JWK in RFC 7518, section 6.2.1.1.
+fn jwk_curve_name(key_name: &str, curve: EccFamily, key_bits: usize) -> Result<&'static str> {
+    match (curve, key_bits) {
+        (EccFamily::SecpR1, 256) => Ok("P-256"),
+        (EccFamily::SecpR1, 384) => Ok("P-384"),
+        (EccFamily::SecpR1, 521) => Ok("P-521"),
+        _ => Err(unsupported_curve(key_name, curve, key_bits, "JWK export")),
+    }
+}
+
+fn curve_oid(key_name: &str, curve: EccFamily, key_bits: usize) -> Result<ObjectIdentifier> {
+    let unsupported = |curve, key_bits| {
+        Err(unsupported_curve(
+            key_name,
+            curve,
+            key_bits,
+            "public key export",
+        ))
+    };
     let curve_oid = match curve {
         // SEC random curves over prime fields.
         EccFamily::SecpR1 => match key_bits {
@@ -110,7 +220,7 @@ fn curve_oid(curve: EccFamily, key_bits: usize) -> Result<ObjectIdentifier> {
             256 => picky_asn1_x509::oids::secp256r1(),
             384 => picky_asn1_x509::oids::secp384r1(),
             521 => picky_asn1_x509::oids::secp521r1(),
-            _ => return print_error(curve, key_bits),
+            _ => return unsupported(curve, key_bits),
         },
         // SEC Koblitz curves over prime fields.
         // OIDs are not defined in picky_asn1_x509::oids and in RFC5480.
@@ -119,7 +229,7 @@ fn curve_oid(curve: EccFamily, key_bits: usize) -> Result<ObjectIdentifier> {
             192 => ObjectIdentifier::try_from("1.3.132.0.31").unwrap(),
             224 => ObjectIdentifier::try_from("1.3.132.0.32").unwrap(),
             256 => ObjectIdentifier::try_from("1.3.132.0.10").unwrap(),
-            _ => return print_error(curve, key_bits),
+            _ => return unsupported(curve, key_bits),
         },
         // SEC Koblitz curves over binary fields
         EccFamily::SectK1 => match key_bits {
@@ -127,7 +237,7 @@ fn curve_oid(curve: EccFamily, key_bits: usize) -> Result<ObjectIdentifier> {
             283 => picky_asn1_x509::oids::sect283k1(),
             409 => picky_asn1_x509::oids::sect409k1(),
             571 => picky_asn1_x509::oids::sect571k1(),
-            _ => return print_error(curve, key_bits),
+            _ => return unsupported(curve, key_bits),
         },
         // SEC random curves over binary fields
         EccFamily::SectR1 => match key_bits {
@@ -135,20 +245,18 @@ fn curve_oid(curve: EccFamily, key_bits: usize) -> Result<ObjectIdentifier> {
             283 => picky_asn1_x509::oids::sect283r1(),
             409 => picky_asn1_x509::oids::sect409r1(),
             571 => picky_asn1_x509::oids::sect571r1(),
-            _ => return print_error(curve, key_bits),
+            _ => return unsupported(curve, key_bits),
         },
-        _ => {
-            error!("Unsupported Ecc family \"{}\"", curve);
-            return Err(ToolErrorKind::NotSupported.into());
-        }
+        _ => return unsupported(curve, key_bits),
     };
     Ok(curve_oid)
 }
 
-fn print_error(curve: EccFamily, key_bits: usize) -> Result<ObjectIdentifier> {
-    error!(
-        "Unsupported number of bits {} for Ecc family \"{}\"",
-        key_bits, curve
-    );
-    Err(ToolErrorKind::NotSupported.into())
+fn unsupported_curve(key_name: &str, curve: EccFamily, key_bits: usize, operation: &str) -> Error {
+    ToolErrorKind::UnsupportedKeyAlgorithm {
+        key_name: key_name.to_string(),
+        algorithm: format!("{} bits keys of Ecc family \"{}\"", key_bits, curve),
+        operation: operation.to_string(),
+    }
+    .into()
 }
@@ -0,0 +1,450 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Inspects a Certificate Signing Request (CSR) or an X509 certificate.
+//!
+//! The input can be PEM or DER encoded. The subject, Subject Alternative Names (SANs), extensions
+//! and public key are printed, and the self-signature is verified locally. Optionally, the public
+//! key embedded in the input can be compared with the public part of a key held by Parsec.
+
+use crate::error::{Result, ToolErrorKind};
+use crate::util::{public_key_matches, read_der};
+use clap::Parser;
+use log::warn;
+use oid::prelude::*;
+use parsec_client::BasicClient;
+use picky_asn1_x509::{
+    oids, AlgorithmIdentifier, AlgorithmIdentifierParameters, AttributeValues, Certificate,
+    CertificationRequest, Extension, ExtensionView, GeneralName, HashAlgorithm, PublicKey,
+    SubjectPublicKeyInfo, Time,
+};
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use std::convert::TryInto;
+use std::mem::size_of;
+use std::path::PathBuf;
+
+/// Inspects and verifies a CSR or a certificate.
+#[derive(Debug, Parser)]
+pub struct Inspect {
+    /// Path to the CSR or certificate to inspect, either PEM or DER encoded.
+    input_file: PathBuf,
+
+    /// The name of a Parsec key. If specified, the public key embedded in the input is compared
+    /// with the public part of this key.
+    #[structopt(short = 'k', long = "key-name")]
+    key_name: Option<String>,
+}
+
+/// The two kinds of documents that can be inspected.
+enum Document {
+    Csr(Box<CertificationRequest>),
+    Certificate(Box<Certificate>),
+}
+
+impl Inspect {
+    /// Inspects and verifies a CSR or a certificate.
+    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+        let der = read_der(&self.input_file)?;
+        let document = parse_document(&der)?;
+
+        let (subject, spki, extensions, signature_algorithm, signature) = match &document {
+            Document::Csr(csr) => {
+                println!("Type: Certificate Signing Request");
+                let info = &csr.certification_request_info;
+                let extensions: Vec<Extension> = info
+                    .attributes
+                    .0
+                    .iter()
+                    .filter_map(|attribute| match &attribute.value {
+                        AttributeValues::Extensions(extensions) => Some(extensions.0.clone()),
+                        _ => None,
+                    })
+                    .flat_map(|set| set.into_iter().flat_map(|extensions| extensions.0))
+                    .collect();
+                (
+                    &info.subject,
+                    &info.subject_public_key_info,
+                    extensions,
+                    &csr.signature_algorithm,
+                    csr.signature.0.payload_view(),
+                )
+            }
+            Document::Certificate(cert) => {
+                println!("Type: Certificate");
+                let tbs = &cert.tbs_certificate;
+                println!(
+                    "Serial number: {}",
+                    to_hex(tbs.serial_number.as_unsigned_bytes_be())
+                );
+                println!("Issuer: {}", tbs.issuer);
+                println!("Not before: {}", format_time(&tbs.validity.not_before));
+                println!("Not after: {}", format_time(&tbs.validity.not_after));
+                (
+                    &tbs.subject,
+                    &tbs.subject_public_key_info,
+                    cert.extensions().to_vec(),
+                    &cert.signature_algorithm,
+                    cert.signature_value.0.payload_view(),
+                )
+            }
+        };
+
+        println!("Subject: {}", subject);
+        print_subject_alternative_names(&extensions);
+        print_extensions(&extensions);
+        print_public_key(spki);
+        println!(
+            "Signature algorithm: {}",
+            oid_string(signature_algorithm.oid())
+        );
+
+        let self_signed = match &document {
+            Document::Csr(_) => true,
+            Document::Certificate(cert) => {
+                cert.tbs_certificate.issuer == cert.tbs_certificate.subject
+            }
+        };
+        let signature_ok = if self_signed {
+            let signed_data = first_der_element(&der)?;
+            match verify_signature(spki, signature_algorithm, signed_data, signature)? {
+                Some(verified) => {
+                    println!(
+                        "Signature: {}",
+                        if verified {
+                            "OK"
+                        } else {
+                            "verification FAILED"
+                        }
+                    );
+                    verified
+                }
+                None => {
+                    println!("Signature: not verified (unsupported algorithm)");
+                    true
+                }
+            }
+        } else {
+            println!("Signature: not verified (the certificate is not self-signed)");
+            true
+        };
+
+        let key_matches = if let Some(key_name) = &self.key_name {
+            let parsec_public_key = basic_client.psa_export_public_key(key_name)?;
+            let matches = public_key_matches(spki, &parsec_public_key);
+            println!(
+                "Public key matches Parsec key \"{}\": {}",
+                key_name,
+                if matches { "yes" } else { "no" }
+            );
+            matches
+        } else {
+            true
+        };
+
+        if !signature_ok {
+            return Err(
+                ToolErrorKind::InvalidSignature("the signature is not valid".to_string()).into(),
+            );
+        }
+        if let (false, Some(key_name)) = (key_matches, &self.key_name) {
+            return Err(ToolErrorKind::InvalidData(format!(
+                "the public key is not the one of Parsec key \"{}\"",
+                key_name
+            ))
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Indicates if the Parsec service is needed to run this command.
+    pub fn needs_service(&self) -> bool {
+        self.key_name.is_some()
+    }
+}
+
+// Parses the DER content of the input as a certificate, or else as a CSR.
+fn parse_document(der: &[u8]) -> Result<Document> {
+    if let Ok(cert) = picky_asn1_der::from_bytes::<Certificate>(der) {
+        return Ok(Document::Certificate(Box::new(cert)));
+    }
+    if let Ok(csr) = picky_asn1_der::from_bytes::<CertificationRequest>(der) {
+        return Ok(Document::Csr(Box::new(csr)));
+    }
+    Err(ToolErrorKind::InvalidData(
+        "the input is neither a certificate nor a certificate signing request".to_string(),
+    )
+    .into())
+}
+
+// Returns the raw DER encoding of the first element of the outer SEQUENCE. This is the
+// to-be-signed part of both certificates and CSRs. It is sliced out of the input rather than
+// re-serialised, so that the signature is checked against the exact bytes that were signed.
+fn first_der_element(der: &[u8]) -> Result<&[u8]> {
+    let (_, outer_header) = der_header(der)?;
+    let inner = &der[outer_header..];
+    let (length, header) = der_header(inner)?;
+    inner
+        .get(..header + length)
+        .ok_or_else(|| ToolErrorKind::InvalidData("truncated DER input".to_string()).into())
+}
+
+// Parses a DER tag and length, returning the content length and the size of the header.
+fn der_header(der: &[u8]) -> Result<(usize, usize)> {
+    let truncated = || ToolErrorKind::InvalidData("truncated DER input".to_string());
+    let first_length_byte = *der.get(1).ok_or_else(truncated)?;
+    if first_length_byte & 0x80 == 0 {
+        return Ok((first_length_byte as usize, 2));
+    }
+    let length_bytes = (first_length_byte & 0x7f) as usize;
+    if length_bytes > size_of::<usize>() {
+        return Err(
+            ToolErrorKind::InvalidData("unsupported DER length encoding".to_string()).into(),
+        );
+    }
+    let length = der
+        .get(2..2 + length_bytes)
+        .ok_or_else(truncated)?
+        .iter()
+        .fold(0usize, |length, byte| (length << 8) | *byte as usize);
+    Ok((length, 2 + length_bytes))
+}
+
+fn print_subject_alternative_names(extensions: &[Extension]) {
+    for extension in extensions {
+        if let ExtensionView::SubjectAltName(names) = extension.extn_value() {
+            println!("Subject Alternative Names:");
+            for name in names.0.iter() {
+                println!("    {}", format_general_name(name));
+            }
+        }
+    }
+}
+
+fn print_extensions(extensions: &[Extension]) {
+    if extensions.is_empty() {
+        return;
+    }
+    println!("Extensions:");
+    for extension in extensions {
+        let oid = oid_string(&extension.extn_id().0);
+        let critical = if extension.critical() {
+            " (critical)"
+        } else {
+            ""
+        };
+        let value = match extension.extn_value() {
+            ExtensionView::SubjectAltName(names) => names
+                .0
+                .iter()
+                .map(format_general_name)
+                .collect::<Vec<String>>()
+                .join(", "),
+            ExtensionView::BasicConstraints(constraints) => format!(
+                "CA: {}, path length: {}",
+                constraints.ca().unwrap_or(false),
+                constraints
+                    .pathlen()
+                    .map_or_else(|| String::from("none"), |pathlen| pathlen.to_string())
+            ),
+            ExtensionView::KeyUsage(key_usage) => to_hex(key_usage.as_bytes()),
+            ExtensionView::ExtendedKeyUsage(usages) => usages
+                .iter()
+                .map(|usage| oid_string(&usage.0))
+                .collect::<Vec<String>>()
+                .join(", "),
+            ExtensionView::SubjectKeyIdentifier(ski) => to_hex(&ski.0),
+            ExtensionView::AuthorityKeyIdentifier(aki) => {
+                aki.key_identifier().map(to_hex).unwrap_or_default()
+            }
+            ExtensionView::Generic(value) => to_hex(&value.0),
+            _ => String::new(),
+        };
+        println!(
+            "    {} {}{}: {}",
+            oid,
+            extension_name(&oid),
+            critical,
+            value
+        );
+    }
+}
+
+fn extension_name(oid: &str) -> &'static str {
+    match oid {
+        oids::SUBJECT_KEY_IDENTIFIER => "(subjectKeyIdentifier)",
+        oids::KEY_USAGE => "(keyUsage)",
+        oids::SUBJECT_ALTERNATIVE_NAME => "(subjectAltName)",
+        oids::ISSUER_ALTERNATIVE_NAME => "(issuerAltName)",
+        oids::BASIC_CONSTRAINTS => "(basicConstraints)",
+        oids::AUTHORITY_KEY_IDENTIFIER => "(authorityKeyIdentifier)",
+        oids::EXTENDED_KEY_USAGE => "(extKeyUsage)",
+        _ => "",
+    }
+}
+
+fn format_general_name(name: &GeneralName) -> String {
+    match name {
+        GeneralName::DnsName(dns) => format!("DNS:{}", String::from_utf8_lossy(dns.as_bytes())),
+        GeneralName::Rfc822Name(email) => {
+            format!("email:{}", String::from_utf8_lossy(email.as_bytes()))
+        }
+        GeneralName::Uri(uri) => format!("URI:{}", String::from_utf8_lossy(uri.as_bytes())),
+        GeneralName::IpAddress(ip) => match ip.0.len() {
+            4 => {
+                let octets: [u8; 4] = ip.0[..].try_into().unwrap();
+                format!("IP:{}", std::net::Ipv4Addr::from(octets))
+            }
+            16 => {
+                let octets: [u8; 16] = ip.0[..].try_into().unwrap();
+                format!("IP:{}", std::net::Ipv6Addr::from(octets))
+            }
+            _ => format!("IP:{}", to_hex(&ip.0)),
+        },
+        GeneralName::DirectoryName(name) => format!("DirName:{}", name),
+        GeneralName::RegisteredId(oid) => format!("RID:{}", oid_string(&oid.0)),
+        other => format!("{:?}", other),
+    }
+}
+
+fn print_public_key(spki: &SubjectPublicKeyInfo) {
+    match &spki.subject_public_key {
+        PublicKey::Rsa(rsa) => {
+            let modulus = rsa.0.modulus.as_unsigned_bytes_be();
+            println!("Public key: RSA ({} bits)", modulus.len() * 8);
+            println!("    Modulus: {}", to_hex(modulus));
+            println!(
+                "    Exponent: {}",
+                to_hex(rsa.0.public_exponent.as_unsigned_bytes_be())
+            );
+        }
+        PublicKey::Ec(point) => {
+            let curve = match spki.algorithm.parameters() {
+                AlgorithmIdentifierParameters::Ec(params) => curve_name(params.curve_oid()),
+                _ => String::from("unknown curve"),
+            };
+            println!("Public key: ECC ({})", curve);
+            println!("    Point: {}", to_hex(point.0.payload_view()));
+        }
+        PublicKey::Ed(point) => {
+            println!("Public key: {}", oid_string(spki.algorithm.oid()));
+            println!("    Point: {}", to_hex(point.0.payload_view()));
+        }
+    }
+}
+
+fn curve_name(oid: &ObjectIdentifier) -> String {
+    let oid = oid_string(oid);
+    match oid.as_str() {
+        oids::SECP192R1 => String::from("secp192r1"),
+        oids::SECP224R1 => String::from("secp224r1"),
+        oids::SECP256R1 => String::from("secp256r1"),
+        oids::SECP384R1 => String::from("secp384r1"),
+        oids::SECP521R1 => String::from("secp521r1"),
+        _ => oid,
+    }
+}
+
+// Maps the signature algorithm of the document to one of ring's verification algorithms. ring has
+// no P-521 curve nor ECDSA with SHA-512, so those signatures cannot be verified.
+fn verification_algorithm(
+    spki: &SubjectPublicKeyInfo,
+    signature_algorithm: &AlgorithmIdentifier,
+) -> Option<&'static dyn VerificationAlgorithm> {
+    let curve = match spki.algorithm.parameters() {
+        AlgorithmIdentifierParameters::Ec(params) => oid_string(params.curve_oid()),
+        _ => String::new(),
+    };
+    let algorithm: &'static dyn VerificationAlgorithm = match (
+        oid_string(signature_algorithm.oid()).as_str(),
+        curve.as_str(),
+    ) {
+        (oids::SHA256_WITH_RSA_ENCRYPTION, _) => &signature::RSA_PKCS1_2048_8192_SHA256,
+        (oids::SHA384_WITH_RSA_ENCRYPTION, _) => &signature::RSA_PKCS1_2048_8192_SHA384,
+        (oids::SHA512_WITH_RSA_ENCRYPTION, _) => &signature::RSA_PKCS1_2048_8192_SHA512,
+        (oids::RSASSA_PSS, _) => match signature_algorithm.parameters() {
+            AlgorithmIdentifierParameters::RsassaPss(params) => match params.hash_algorithm {
+                HashAlgorithm::SHA256 => &signature::RSA_PSS_2048_8192_SHA256,
+                HashAlgorithm::SHA384 => &signature::RSA_PSS_2048_8192_SHA384,
+                HashAlgorithm::SHA512 => &signature::RSA_PSS_2048_8192_SHA512,
+                _ => return None,
+            },
+            _ => return None,
+        },
+        (oids::ECDSA_WITH_SHA256, oids::SECP256R1) => &signature::ECDSA_P256_SHA256_ASN1,
+        (oids::ECDSA_WITH_SHA384, oids::SECP256R1) => &signature::ECDSA_P256_SHA384_ASN1,
+        (oids::ECDSA_WITH_SHA256, oids::SECP384R1) => &signature::ECDSA_P384_SHA256_ASN1,
+        (oids::ECDSA_WITH_SHA384, oids::SECP384R1) => &signature::ECDSA_P384_SHA384_ASN1,
+        _ => return None,
+    };
+    Some(algorithm)
+}
+
+// Verifies the self-signature of the document. Returns None if the algorithm is not supported,
+// which includes RSA keys shorter than the 2048 bits ring needs.
+fn verify_signature(
+    spki: &SubjectPublicKeyInfo,
+    signature_algorithm: &AlgorithmIdentifier,
+    signed_data: &[u8],
+    signature: &[u8],
+) -> Result<Option<bool>> {
+    let algorithm = match verification_algorithm(spki, signature_algorithm) {
+        Some(algorithm) => algorithm,
+        None => {
+            warn!(
+                "Signature algorithm {} is not supported for verification",
+                oid_string(signature_algorithm.oid())
+            );
+            return Ok(None);
+        }
+    };
+    let public_key = match &spki.subject_public_key {
+        PublicKey::Rsa(rsa) if rsa.0.modulus.as_unsigned_bytes_be().len() * 8 < 2048 => {
+            warn!("RSA keys shorter than 2048 bits are not supported for verification");
+            return Ok(None);
+        }
+        PublicKey::Rsa(rsa) => picky_asn1_der::to_vec(&rsa.0).map_err(|_| {
+            ToolErrorKind::InvalidData("could not serialise the RSA key".to_string())
+        })?,
+        PublicKey::Ec(point) | PublicKey::Ed(point) => point.0.payload_view().to_vec(),
+    };
+    Ok(Some(
+        UnparsedPublicKey::new(algorithm, public_key)
+            .verify(signed_data, signature)
+            .is_ok(),
+    ))
+}
+
+fn format_time(time: &Time) -> String {
+    match time {
+        Time::Utc(time) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            time.0.year(),
+            time.0.month(),
+            time.0.day(),
+            time.0.hour(),
+            time.0.minute(),
+            time.0.second()
+        ),
+        Time::Generalized(time) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            time.0.year(),
+            time.0.month(),
+            time.0.day(),
+            time.0.hour(),
+            time.0.minute(),
+            time.0.second()
+        ),
+    }
+}
+
+fn oid_string(oid: &ObjectIdentifier) -> String {
+    oid.into()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<String>>()
+        .join(":")
+}
@@ -3,10 +3,12 @@
 
 //! List the authenticators supported by the Parsec service.
 
+use crate::cli::OutputFormat;
 use crate::error::Result;
 use clap::Parser;
 use log::info;
 use parsec_client::BasicClient;
+use serde_json::{json, Value};
 
 /// List the authenticators supported by the Parsec service.
 #[derive(Debug, Parser)]
@@ -14,9 +16,30 @@ pub struct ListAuthenticators {}
 
 impl ListAuthenticators {
     /// Lists the available authenticators supported by the Parsec service.
-    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+    pub fn run(&self, basic_client: BasicClient, format: OutputFormat) -> Result<()> {
         let authenticators = basic_client.list_authenticators()?;
 
+        if format == OutputFormat::Json {
+            let authenticators: Vec<Value> = authenticators
+                .iter()
+                .map(|authenticator| {
+                    json!({
+                        "id": authenticator.id as u8,
+                        "name": authenticator.id.to_string(),
+                        "description": authenticator.description,
+                        "version": format!(
+                            "{}.{}.{}",
+                            authenticator.version_maj,
+                            authenticator.version_min,
+                            authenticator.version_rev
+                        ),
+                    })
+                })
+                .collect();
+            println!("{}", Value::from(authenticators));
+            return Ok(());
+        }
+
         info!("Available authenticators:");
         for authenticator in authenticators {
             println!(
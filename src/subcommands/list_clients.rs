@@ -3,19 +3,26 @@
 
 //! Lists all clients currently having data in the service (admin operation).
 
+use crate::cli::OutputFormat;
 use crate::error::Result;
 use clap::Parser;
 use log::info;
 use parsec_client::BasicClient;
+use serde_json::Value;
 
 /// Lists all clients currently having data in the service (admin operation).
 #[derive(Debug, Parser)]
 pub struct ListClients {}
 
 impl ListClients {
-    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+    pub fn run(&self, basic_client: BasicClient, format: OutputFormat) -> Result<()> {
         let clients = basic_client.list_clients()?;
 
+        if format == OutputFormat::Json {
+            println!("{}", Value::from(clients));
+            return Ok(());
+        }
+
         if clients.is_empty() {
             info!("No clients in the service.");
             return Ok(());
@@ -3,10 +3,12 @@
 
 //! Lists all keys belonging to the application.
 
+use crate::cli::OutputFormat;
 use crate::error::Result;
 use clap::Parser;
 use log::info;
 use parsec_client::BasicClient;
+use serde_json::{json, Value};
 
 /// Lists all keys belonging to the application.
 #[derive(Debug, Parser)]
@@ -14,9 +16,27 @@ pub struct ListKeys {}
 
 impl ListKeys {
     /// Lists the available providers supported by the Parsec service.
-    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+    pub fn run(&self, basic_client: BasicClient, format: OutputFormat) -> Result<()> {
         let keys = basic_client.list_keys()?;
 
+        if format == OutputFormat::Json {
+            let keys: Vec<Value> = keys
+                .iter()
+                .map(|key| {
+                    let policy = &key.attributes.policy;
+                    json!({
+                        "name": key.name,
+                        "provider": key.provider_id.to_string(),
+                        "type": format!("{:?}", key.attributes.key_type),
+                        "bits": key.attributes.bits,
+                        "permitted_algorithm": format!("{:?}", policy.permitted_algorithms),
+                    })
+                })
+                .collect();
+            println!("{}", Value::from(keys));
+            return Ok(());
+        }
+
         if keys.is_empty() {
             info!("No keys currently available.");
             return Ok(());
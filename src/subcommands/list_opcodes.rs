@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //! Lists the supported opcodes for a given provider.
+use crate::cli::OutputFormat;
 use crate::error::Result;
 use clap::Parser;
 use log::info;
 use parsec_client::BasicClient;
+use serde_json::{json, Value};
 use std::convert::TryInto;
 
 /// Lists the supported opcodes for a given provider.
@@ -18,13 +20,24 @@ pub struct ListOpcodes {
 
 impl ListOpcodes {
     /// Lists the supported opcodes for a given provider.
-    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+    pub fn run(&self, basic_client: BasicClient, format: OutputFormat) -> Result<()> {
         let provider = match self.provider {
             Some(provider) => provider.try_into()?,
             None => basic_client.implicit_provider(),
         };
         let opcodes = basic_client.list_opcodes(provider)?;
 
+        if format == OutputFormat::Json {
+            let mut opcodes: Vec<_> = opcodes.into_iter().collect();
+            opcodes.sort_by_key(|opcode| *opcode as u32);
+            let opcodes: Vec<Value> = opcodes
+                .iter()
+                .map(|opcode| json!({ "id": *opcode as u32, "name": format!("{:?}", opcode) }))
+                .collect();
+            println!("{}", Value::from(opcodes));
+            return Ok(());
+        }
+
         info!("Available opcodes for {}:", provider);
         for provider_opcode in opcodes {
             println!("0x{:02x} ({:?})", provider_opcode as u32, provider_opcode);
@@ -3,10 +3,12 @@
 
 //! Lists the available providers supported by the Parsec service.
 
+use crate::cli::OutputFormat;
 use crate::error::Result;
 use clap::Parser;
 use log::info;
 use parsec_client::BasicClient;
+use serde_json::{json, Value};
 
 /// Lists the available providers supported by the Parsec service.
 #[derive(Debug, Parser)]
@@ -14,9 +16,30 @@ pub struct ListProviders {}
 
 impl ListProviders {
     /// Lists the available providers supported by the Parsec service.
-    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+    pub fn run(&self, basic_client: BasicClient, format: OutputFormat) -> Result<()> {
         let providers = basic_client.list_providers()?;
 
+        if format == OutputFormat::Json {
+            let providers: Vec<Value> = providers
+                .iter()
+                .map(|provider| {
+                    json!({
+                        "id": provider.id as u8,
+                        "name": provider.id.to_string(),
+                        "description": provider.description,
+                        "version": format!(
+                            "{}.{}.{}",
+                            provider.version_maj, provider.version_min, provider.version_rev
+                        ),
+                        "vendor": provider.vendor,
+                        "uuid": provider.uuid.to_string(),
+                    })
+                })
+                .collect();
+            println!("{}", Value::from(providers));
+            return Ok(());
+        }
+
         info!("Available providers:");
         for provider in providers {
             println!("ID: 0x{:02x} ({})", provider.id as u32, provider.id);
@@ -3,15 +3,18 @@
 
 //! Subcommand implementations. Interacts with parsec-client-rust.
 
+mod bench;
 mod create_csr;
 mod create_ecc_key;
 mod create_rsa_key;
 mod decrypt;
 mod delete_client;
 mod delete_key;
+mod doctor;
 mod encrypt;
 mod export_public_key;
 mod generate_random;
+mod inspect;
 mod list_authenticators;
 mod list_clients;
 mod list_keys;
@@ -19,16 +22,26 @@ mod list_opcodes;
 mod list_providers;
 mod ping;
 mod sign;
+mod sign_jwt;
+mod ssh_agent;
+mod verify;
+mod whoami;
 
-use crate::error::{Error::ParsecClientError, Result};
+use crate::cli::{AuthMethod, OutputFormat};
+use crate::error::{Result, ToolErrorKind};
 use crate::subcommands::{
-    create_csr::CreateCsr, create_ecc_key::CreateEccKey, create_rsa_key::CreateRsaKey,
-    decrypt::Decrypt, delete_client::DeleteClient, delete_key::DeleteKey, encrypt::Encrypt,
-    export_public_key::ExportPublicKey, generate_random::GenerateRandom,
-    list_authenticators::ListAuthenticators, list_clients::ListClients, list_keys::ListKeys,
-    list_opcodes::ListOpcodes, list_providers::ListProviders, ping::Ping, sign::Sign,
+    bench::Bench, create_csr::CreateCsr, create_ecc_key::CreateEccKey,
+    create_rsa_key::CreateRsaKey, decrypt::Decrypt, delete_client::DeleteClient,
+    delete_key::DeleteKey, doctor::Doctor, encrypt::Encrypt, export_public_key::ExportPublicKey,
+    generate_random::GenerateRandom, inspect::Inspect, list_authenticators::ListAuthenticators,
+    list_clients::ListClients, list_keys::ListKeys, list_opcodes::ListOpcodes,
+    list_providers::ListProviders, ping::Ping, sign::Sign, sign_jwt::SignJwt, ssh_agent::SshAgent,
+    verify::Verify, whoami::Whoami,
 };
 use clap::Parser;
+use parsec_client::auth::Authentication;
+use parsec_client::core::interface::requests::{AuthType, ProviderId};
+use parsec_client::error::{ClientErrorKind, Error as ClientError};
 use parsec_client::BasicClient;
 
 /// Command-line interface to Parsec operations.
@@ -52,21 +65,28 @@ pub enum Subcommand {
     /// Generate a sequence of random bytes.
     GenerateRandom(GenerateRandom),
 
-    /// Export the public part of the key pair in PEM format
+    /// Export the public part of the key pair (PEM by default, or DER, PKCS#1, OpenSSH, JWK, raw)
     ExportPublicKey(ExportPublicKey),
 
     /// Create a RSA key pair (2048 bits). Used by default for asymmetric encryption with RSA PKCS#1 v1.5.
     CreateRsaKey(CreateRsaKey),
 
-    /// Create a ECC key pair (curve secp256r1). Used by default for asymmetric signing with ECDSA (SHA-256).
+    /// Create an ECC key pair (curve secp256r1 by default, or secp384r1/secp521r1 with --bits) for
+    /// ECDSA signing with the hash of the curve strength, or for ECDH with --for-key-agreement.
     CreateEccKey(CreateEccKey),
 
     /// Decrypt data using the algorithm of the key
     Decrypt(Decrypt),
 
-    /// Sign data using the algorithm of the key (base64 signature)
+    /// Sign data using the algorithm of the key (base64 signature, OpenSSH SSHSIG, CMS or COSE)
     Sign(Sign),
 
+    /// Verify a signature made with the sign command
+    Verify(Verify),
+
+    /// Sign a JSON Web Token (compact JWS) with the algorithm of the key (RS256, PS256, ES256, ES384)
+    SignJwt(SignJwt),
+
     /// Delete a key.
     DeleteKey(DeleteKey),
 
@@ -81,28 +101,59 @@ pub enum Subcommand {
 
     /// Encrypt data using the algorithm of the key
     Encrypt(Encrypt),
+
+    /// Inspect a CSR or certificate (PEM or DER) and verify its self-signature.
+    Inspect(Inspect),
+
+    /// Run an SSH agent serving the keys of the application on a Unix socket.
+    SshAgent(SshAgent),
+
+    /// Show the service endpoint, the authenticator, the identity and the provider used.
+    #[structopt(alias = "session-info")]
+    Whoami(Whoami),
+
+    /// Check the health of the service, probing each provider with throwaway keys.
+    Doctor(Doctor),
+
+    /// Benchmark the operations of the providers, reporting latency percentiles and throughput.
+    Bench(Bench),
 }
 
 impl Subcommand {
-    /// Runs the subcommand.
-    pub fn run(&self, client: BasicClient) -> Result<()> {
+    /// Runs the subcommand, printing information in the given format. The provider is the one
+    /// selected on the command line or in the profile, if any. JWT-SVIDs fetched by the tool use
+    /// the given audience.
+    pub fn run(
+        &self,
+        client: BasicClient,
+        format: OutputFormat,
+        provider: Option<ProviderId>,
+        spiffe_audience: &str,
+    ) -> Result<()> {
         match &self {
-            Subcommand::Ping(cmd) => cmd.run(client),
-            Subcommand::ListProviders(cmd) => cmd.run(client),
-            Subcommand::ListAuthenticators(cmd) => cmd.run(client),
-            Subcommand::ListKeys(cmd) => cmd.run(client),
-            Subcommand::ListClients(cmd) => cmd.run(client),
+            Subcommand::Ping(cmd) => cmd.run(client, format),
+            Subcommand::ListProviders(cmd) => cmd.run(client, format),
+            Subcommand::ListAuthenticators(cmd) => cmd.run(client, format),
+            Subcommand::ListKeys(cmd) => cmd.run(client, format),
+            Subcommand::ListClients(cmd) => cmd.run(client, format),
             Subcommand::DeleteClient(cmd) => cmd.run(client),
-            Subcommand::ListOpcodes(cmd) => cmd.run(client),
+            Subcommand::ListOpcodes(cmd) => cmd.run(client, format),
             Subcommand::GenerateRandom(cmd) => cmd.run(client),
             Subcommand::ExportPublicKey(cmd) => cmd.run(client),
             Subcommand::CreateRsaKey(cmd) => cmd.run(client),
             Subcommand::CreateEccKey(cmd) => cmd.run(client),
             Subcommand::Sign(cmd) => cmd.run(client),
+            Subcommand::Verify(cmd) => cmd.run(client),
+            Subcommand::SignJwt(cmd) => cmd.run(client),
             Subcommand::Decrypt(cmd) => cmd.run(client),
             Subcommand::DeleteKey(cmd) => cmd.run(client),
             Subcommand::CreateCsr(cmd) => cmd.run(client),
             Subcommand::Encrypt(cmd) => cmd.run(client),
+            Subcommand::Inspect(cmd) => cmd.run(client),
+            Subcommand::SshAgent(cmd) => cmd.run(client),
+            Subcommand::Whoami(cmd) => cmd.run(client, format, spiffe_audience),
+            Subcommand::Doctor(cmd) => cmd.run(client, format, provider),
+            Subcommand::Bench(cmd) => cmd.run(client, format, provider),
         }
     }
     /// Indicates if subcommand requires authentication
@@ -117,18 +168,116 @@ impl Subcommand {
         )
     }
 
+    /// Indicates if subcommand needs to contact the Parsec service at all
+    fn service_required(&self) -> bool {
+        match &self {
+            // Inspecting a file only needs the service to compare with a Parsec key.
+            Subcommand::Inspect(cmd) => cmd.needs_service(),
+            // ECIES encryption to a public key is made locally.
+            Subcommand::Encrypt(cmd) => cmd.needs_service(),
+            _ => true,
+        }
+    }
+
     /// Get BasicClient for operation
-    pub fn create_client(&self, app_name: Option<String>) -> Result<BasicClient> {
-        let client_result = if self.authentication_required() {
-            // BasicClient::new will do default config including setting up authenticator
-            BasicClient::new(app_name)
+    ///
+    /// If `auth` is given, that authenticator is used instead of the first one supported. JWT-SVIDs
+    /// are expected with the given audience.
+    #[cfg_attr(not(feature = "spiffe-auth"), allow(unused_variables))]
+    pub fn create_client(
+        &self,
+        app_name: Option<String>,
+        auth: Option<AuthMethod>,
+        spiffe_audience: &str,
+    ) -> Result<BasicClient> {
+        let client = if !self.service_required() {
+            // The client will not be used, so do not try to connect to the service
+            BasicClient::default()
+        } else if self.authentication_required() {
+            match auth {
+                Some(auth) => client_with_auth(app_name, auth)?,
+                // BasicClient::new will do default config including setting up authenticator
+                None => BasicClient::new(app_name)?,
+            }
         } else {
             // Create a naked client which should be set up for core operations with no authenticator
-            BasicClient::new_naked()
+            BasicClient::new_naked()?
         };
-        match client_result {
-            Ok(client) => Ok(client),
-            Err(err) => Err(ParsecClientError(err)),
+        #[cfg(feature = "spiffe-auth")]
+        if let Authentication::JwtSvid = client.auth_data() {
+            check_spiffe_audience(spiffe_audience);
+        }
+        Ok(client)
+    }
+}
+
+/// Warns if the JWT-SVIDs of the tool use another audience than the one the client authenticates
+/// its requests with, which is always the default one.
+#[cfg(feature = "spiffe-auth")]
+fn check_spiffe_audience(spiffe_audience: &str) {
+    use crate::cli::DEFAULT_SPIFFE_AUDIENCE;
+    use log::warn;
+
+    if spiffe_audience != DEFAULT_SPIFFE_AUDIENCE {
+        warn!(
+            "Requests are authenticated with the \"{}\" audience, not \"{}\"",
+            DEFAULT_SPIFFE_AUDIENCE, spiffe_audience
+        );
+    }
+}
+
+/// Creates a client using the given authenticator, after checking that the service enables it.
+fn client_with_auth(app_name: Option<String>, auth: AuthMethod) -> Result<BasicClient> {
+    let mut client = BasicClient::new_naked()?;
+    client.set_default_provider()?;
+
+    let (auth_type, auth_data) = match auth {
+        AuthMethod::Direct => (
+            AuthType::Direct,
+            Authentication::Direct(
+                app_name.ok_or(ClientError::Client(ClientErrorKind::MissingParam))?,
+            ),
+        ),
+        AuthMethod::UnixPeer => (
+            AuthType::UnixPeerCredentials,
+            Authentication::UnixPeerCredentials,
+        ),
+        #[cfg(feature = "spiffe-auth")]
+        AuthMethod::JwtSvid => (AuthType::JwtSvid, Authentication::JwtSvid),
+        #[cfg(not(feature = "spiffe-auth"))]
+        AuthMethod::JwtSvid => {
+            return Err(ToolErrorKind::UnsupportedOperation(
+                "JWT-SVID authentication needs the tool to be built with the spiffe-auth feature"
+                    .to_string(),
+            )
+            .into());
+        }
+        AuthMethod::None => {
+            client.set_auth_data(Authentication::None);
+            return Ok(client);
         }
+    };
+
+    let authenticators = client.list_authenticators()?;
+    if !authenticators
+        .iter()
+        .any(|authenticator| authenticator.id == auth_type)
+    {
+        let enabled: Vec<String> = authenticators
+            .iter()
+            .map(|authenticator| authenticator.id.to_string())
+            .collect();
+        return Err(ToolErrorKind::AuthenticatorNotEnabled {
+            requested: auth_type.to_string(),
+            enabled: if enabled.is_empty() {
+                String::from("none")
+            } else {
+                enabled.join(", ")
+            },
+        }
+        .into());
     }
+
+    client.set_auth_data(auth_data);
+    Ok(client)
 }
@@ -3,10 +3,12 @@
 
 //! Pings the Parsec service.
 
+use crate::cli::OutputFormat;
 use crate::error::Result;
 use clap::Parser;
 use log::info;
 use parsec_client::BasicClient;
+use serde_json::json;
 
 /// Pings the Parsec service.
 #[derive(Debug, Parser)]
@@ -14,11 +16,19 @@ pub struct Ping {}
 
 impl Ping {
     /// Pings the Parsec service and prints the wire protocol version.
-    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+    pub fn run(&self, basic_client: BasicClient, format: OutputFormat) -> Result<()> {
         let result = basic_client.ping()?;
 
-        info!("Service wire protocol version",);
-        println!("{}.{}", result.0, result.1);
+        match format {
+            OutputFormat::Text => {
+                info!("Service wire protocol version",);
+                println!("{}.{}", result.0, result.1);
+            }
+            OutputFormat::Json => println!(
+                "{}",
+                json!({ "wire_protocol_version": format!("{}.{}", result.0, result.1) })
+            ),
+        }
         Ok(())
     }
 }
@@ -4,11 +4,34 @@
 //! Signs data.
 //!
 //! Will use the algorithm set to the key's policy during creation.
+//!
+//! CMS signatures are detached: the SignedData holds the signer's certificate and signed
+//! attributes (content type, message digest and signing time), but not the signed content.
+//! COSE_Sign1 signatures use the COSE algorithm (ES256, ES384 or PS256) matching the key's policy.
+//!
+//! A digest computed beforehand, given in hexadecimal, can be signed instead of data. The signature
+//! is then in the base64 format.
 
-use crate::error::Result;
-use crate::util::sign_message_with_policy;
+use crate::cms;
+use crate::cose::{self, CoseSign1};
+use crate::error::{Result, ToolErrorKind};
+use crate::ssh::{
+    ecdsa_signature_blob, public_key_blob, put_string, signature_scheme, SshSignature,
+};
+use crate::util::{
+    check_policy_hash, decode_hex, print_pem, public_key_matches, read_der, read_input,
+    sign_hash_with_policy, sign_message_with_policy, write_binary, HashAlgorithm, Input,
+    SignatureEncoding, SignatureFormat,
+};
 use clap::Parser;
+use parsec_client::core::interface::operations::psa_algorithm::{Algorithm, Hash, SignHash};
 use parsec_client::BasicClient;
+use picky_asn1_x509::Certificate;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+// Hash used by ssh-keygen for the messages of SSH signatures.
+const SSHSIG_HASH_ALGORITHM: &str = "sha512";
 
 /// Signs data.
 #[derive(Debug, Parser)]
@@ -17,23 +40,258 @@ pub struct Sign {
     key_name: String,
 
     /// String of UTF-8 text
-    input_data: String,
+    #[structopt(required_unless_present_any = ["input_file", "digest"])]
+    input_data: Option<String>,
+
+    /// File containing the data to sign, instead of a string
+    #[structopt(short = 'i', long = "input-file", conflicts_with = "input_data")]
+    input_file: Option<PathBuf>,
+
+    /// Digest to sign, in hexadecimal, instead of data. The hash algorithm that computed it is
+    /// given with --hash.
+    #[structopt(long = "digest", conflicts_with_all = ["input_data", "input_file"])]
+    digest: Option<String>,
+
+    /// Hash algorithm of the digest, or of the data for keys allowing any hash algorithm or raw
+    /// signatures (SHA-256 by default for those). Keys restricted to one hash algorithm only
+    /// accept that one. Not accepted for SSH and COSE signatures, whose hash follows the key.
+    #[structopt(long = "hash")]
+    hash: Option<HashAlgorithm>,
+
+    /// Format of the signature
+    #[structopt(short = 'f', long = "format", default_value = "base64")]
+    format: SignatureFormat,
+
+    /// Encoding of ECDSA signatures in the base64 format: DER (default) or raw r||s
+    #[structopt(long = "sig-format")]
+    sig_format: Option<SignatureEncoding>,
+
+    /// Namespace of an SSH signature, for example "git" or "file"
+    #[structopt(short = 'n', long = "namespace", required_if_eq("format", "sshsig"))]
+    namespace: Option<String>,
+
+    /// Certificate of the key (PEM or DER), included in CMS signatures
+    #[structopt(
+        short = 'c',
+        long = "certificate",
+        required_if_eq_any([("format", "cms"), ("format", "cms-der")])
+    )]
+    certificate: Option<PathBuf>,
+
+    /// Key identifier (kid) to put in the header of a COSE signature
+    #[structopt(long = "kid")]
+    kid: Option<String>,
+
+    /// Put the key identifier in the protected header of a COSE signature, instead of the
+    /// unprotected one
+    #[structopt(long = "protected-kid", requires = "kid")]
+    protected_kid: bool,
 }
 
 impl Sign {
     /// Signs data.
     pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+        if self.sig_format.is_some() && self.format != SignatureFormat::Base64 {
+            return Err(ToolErrorKind::UnsupportedOperation(
+                "the signature encoding can only be chosen for base64 signatures".to_string(),
+            )
+            .into());
+        }
+        if self.hash.is_some()
+            && (self.format == SignatureFormat::Sshsig || self.format == SignatureFormat::Cose)
+        {
+            return Err(ToolErrorKind::UnsupportedOperation(
+                "the hash algorithm of SSH and COSE signatures follows the key".to_string(),
+            )
+            .into());
+        }
+        if let Some(digest) = &self.digest {
+            if self.format != SignatureFormat::Base64 {
+                return Err(ToolErrorKind::UnsupportedOperation(
+                    "only base64 signatures can be made from a digest".to_string(),
+                )
+                .into());
+            }
+            let signature = sign_hash_with_policy(
+                &basic_client,
+                &self.key_name,
+                &decode_hex(digest)?,
+                self.hash.map(Hash::from),
+                self.sig_format.unwrap_or(SignatureEncoding::Der),
+            )?;
+
+            println!("{}", base64::encode(signature));
+
+            return Ok(());
+        }
+        let input = Input::open(&self.input_data, &self.input_file)?;
+        if let Some(hash) = self.hash {
+            check_policy_hash(&basic_client, &self.key_name, hash.into())?;
+        }
+
+        match self.format {
+            SignatureFormat::Base64 => {
+                let signature = sign_message_with_policy(
+                    &basic_client,
+                    &self.key_name,
+                    input,
+                    Some(self.hash.map_or(Hash::Sha256, Hash::from)),
+                    self.sig_format.unwrap_or(SignatureEncoding::Der),
+                )?;
+
+                let signature = base64::encode(signature);
+
+                println!("{}", signature);
+            }
+            SignatureFormat::Sshsig => {
+                let signature = self.sign_sshsig(&basic_client, input)?;
+
+                print!("{}", signature.to_armored());
+            }
+            SignatureFormat::Cms => print_pem("CMS", self.sign_cms(&basic_client, input)?),
+            SignatureFormat::CmsDer => write_binary(&self.sign_cms(&basic_client, input)?)?,
+            // The content is embedded in COSE signatures, so it is read in memory.
+            SignatureFormat::Cose => write_binary(&self.sign_cose(
+                &basic_client,
+                &read_input(&self.input_data, &self.input_file)?,
+            )?)?,
+        }
+
+        Ok(())
+    }
+
+    fn sign_sshsig(&self, basic_client: &BasicClient, input: Input<'_>) -> Result<SshSignature> {
+        let namespace = match &self.namespace {
+            Some(namespace) => namespace.clone(),
+            None => {
+                return Err(ToolErrorKind::MissingInput(
+                    "SSH signatures need a namespace".to_string(),
+                )
+                .into());
+            }
+        };
+        let attributes = basic_client.key_attributes(&self.key_name)?;
+        let (hash, signature_type) = signature_scheme(&self.key_name, &attributes)?;
+        let public_key = basic_client.psa_export_public_key(&self.key_name)?;
+        let (_, public_key) = public_key_blob(&self.key_name, &attributes, &public_key)?;
+
+        let signed_data = SshSignature::signed_data(&namespace, SSHSIG_HASH_ALGORITHM, input)?;
         let signature = sign_message_with_policy(
-            &basic_client,
+            basic_client,
             &self.key_name,
-            self.input_data.as_bytes(),
-            None,
+            &signed_data,
+            Some(hash),
+            SignatureEncoding::Raw,
         )?;
+        let signature = if attributes.key_type.is_ecc_key_pair() {
+            ecdsa_signature_blob(&signature_type, &signature)
+        } else {
+            let mut blob = Vec::new();
+            put_string(&mut blob, signature_type.as_bytes());
+            put_string(&mut blob, &signature);
+            blob
+        };
+
+        Ok(SshSignature {
+            public_key,
+            namespace,
+            hash_algorithm: String::from(SSHSIG_HASH_ALGORITHM),
+            signature,
+        })
+    }
 
-        let signature = base64::encode(signature);
+    fn sign_cms(&self, basic_client: &BasicClient, input: Input<'_>) -> Result<Vec<u8>> {
+        let certificate = match &self.certificate {
+            Some(certificate) => read_der(certificate)?,
+            None => {
+                return Err(ToolErrorKind::MissingInput(
+                    "CMS signatures need the certificate of the key".to_string(),
+                )
+                .into());
+            }
+        };
+        let parsed_certificate: Certificate =
+            picky_asn1_der::from_bytes(&certificate).map_err(|_| {
+                ToolErrorKind::InvalidData("could not parse the certificate".to_string())
+            })?;
+        let public_key = basic_client.psa_export_public_key(&self.key_name)?;
+        if !public_key_matches(
+            &parsed_certificate.tbs_certificate.subject_public_key_info,
+            &public_key,
+        ) {
+            return Err(ToolErrorKind::InvalidData(format!(
+                "the certificate is not the certificate of key \"{}\"",
+                self.key_name
+            ))
+            .into());
+        }
 
-        println!("{}", signature);
+        let alg = match basic_client
+            .key_attributes(&self.key_name)?
+            .policy
+            .permitted_algorithms
+        {
+            Algorithm::AsymmetricSignature(alg) => alg,
+            other => {
+                return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                    key_name: self.key_name.clone(),
+                    expected: "an asymmetric signature algorithm".to_string(),
+                    actual: format!("{:?}", other),
+                }
+                .into());
+            }
+        };
+        // The same hash is used for the content and the signed attributes.
+        let hash = match alg.hash() {
+            Some(SignHash::Specific(hash)) => hash,
+            _ => self.hash.map_or(Hash::Sha256, Hash::from),
+        };
 
-        Ok(())
+        let signed_attributes = cms::signed_attributes(input, hash, SystemTime::now())?;
+        let signature = sign_message_with_policy(
+            basic_client,
+            &self.key_name,
+            &cms::signed_attributes_data(&signed_attributes)?,
+            Some(hash),
+            SignatureEncoding::Der,
+        )?;
+
+        cms::signed_data(
+            &certificate,
+            hash,
+            cms::signature_algorithm(&self.key_name, alg, hash)?,
+            signed_attributes,
+            signature,
+        )
+    }
+
+    fn sign_cose(&self, basic_client: &BasicClient, input: &[u8]) -> Result<Vec<u8>> {
+        let (alg, hash) = cose::algorithm(
+            &self.key_name,
+            &basic_client.key_attributes(&self.key_name)?,
+        )?;
+        let kid = self.kid.as_ref().map(|kid| kid.as_bytes());
+        let (protected_kid, unprotected_kid) = if self.protected_kid {
+            (kid, None)
+        } else {
+            (None, kid)
+        };
+
+        let mut message = CoseSign1 {
+            protected: CoseSign1::protected_header(alg, protected_kid),
+            unprotected_kid: unprotected_kid.map(<[u8]>::to_vec),
+            payload: input.to_vec(),
+            signature: Vec::new(),
+        };
+        // COSE needs the raw form of ECDSA signatures (RFC 9053, section 2.1).
+        message.signature = sign_message_with_policy(
+            basic_client,
+            &self.key_name,
+            &message.to_be_signed(),
+            Some(hash),
+            SignatureEncoding::Raw,
+        )?;
+
+        Ok(message.encode())
     }
 }
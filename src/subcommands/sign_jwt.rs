@@ -0,0 +1,165 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signs a JSON Web Token (JWT).
+//!
+//! The claims are signed in a compact JSON Web Signature (JWS, RFC 7515). The JOSE algorithm (RS256,
+//! PS256, ES256 or ES384) is chosen from the key's policy.
+
+use crate::error::{Result, ToolErrorKind};
+use crate::util::{base64_url, sign_message_with_policy, SignatureEncoding};
+use clap::Parser;
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricSignature, Hash, SignHash,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{Attributes, EccFamily, Type};
+use parsec_client::BasicClient;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Signs a JSON Web Token (JWT).
+#[derive(Debug, Parser)]
+pub struct SignJwt {
+    #[structopt(short = 'k', long = "key-name")]
+    key_name: String,
+
+    /// Claims of the token, as a JSON object
+    #[structopt(default_value = "{}")]
+    claims: String,
+
+    /// Set the expiration time ("exp" claim) to the given number of seconds from now
+    #[structopt(long = "exp")]
+    expires_in: Option<u64>,
+
+    /// Set the issued at time ("iat" claim) to now
+    #[structopt(long = "iat")]
+    issued_at: bool,
+
+    /// Set the not before time ("nbf" claim) to now
+    #[structopt(long = "nbf")]
+    not_before: bool,
+}
+
+impl SignJwt {
+    /// Signs a JSON Web Token (JWT).
+    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+        let mut claims: Value = serde_json::from_str(&self.claims).map_err(|e| {
+            ToolErrorKind::InvalidData(format!("could not parse the claims: {}", e))
+        })?;
+        let claims_map = match claims.as_object_mut() {
+            Some(claims_map) => claims_map,
+            None => {
+                return Err(ToolErrorKind::InvalidData(
+                    "the claims must be a JSON object".to_string(),
+                )
+                .into());
+            }
+        };
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| {
+                ToolErrorKind::InvalidData("the system time is before the Unix epoch".to_string())
+            })?
+            .as_secs();
+        if let Some(expires_in) = self.expires_in {
+            let _ = claims_map.insert(String::from("exp"), json!(now + expires_in));
+        }
+        if self.issued_at {
+            let _ = claims_map.insert(String::from("iat"), json!(now));
+        }
+        if self.not_before {
+            let _ = claims_map.insert(String::from("nbf"), json!(now));
+        }
+
+        let attributes = basic_client.key_attributes(&self.key_name)?;
+        let (alg, hash) = jose_algorithm(&self.key_name, &attributes)?;
+        let header = json!({
+            "alg": alg,
+            "typ": "JWT",
+        });
+
+        let signing_input = format!(
+            "{}.{}",
+            base64_url(header.to_string().as_bytes()),
+            base64_url(claims.to_string().as_bytes())
+        );
+        // JWS needs the raw form of ECDSA signatures (RFC 7518, section 3.4).
+        let signature = sign_message_with_policy(
+            &basic_client,
+            &self.key_name,
+            signing_input.as_bytes(),
+            Some(hash),
+            SignatureEncoding::Raw,
+        )?;
+
+        println!("{}.{}", signing_input, base64_url(&signature));
+
+        Ok(())
+    }
+}
+
+/// Returns the JOSE algorithm (RFC 7518) matching the key's policy, with its hash.
+fn jose_algorithm(key_name: &str, attributes: &Attributes) -> Result<(&'static str, Hash)> {
+    // ES256 and ES384 are only defined on the NIST curves.
+    let secp_r1 = matches!(
+        attributes.key_type,
+        Type::EccKeyPair {
+            curve_family: EccFamily::SecpR1
+        }
+    );
+    let jose_algorithm = match attributes.policy.permitted_algorithms {
+        Algorithm::AsymmetricSignature(alg) => match (alg, attributes.bits) {
+            (
+                AsymmetricSignature::RsaPkcs1v15Sign {
+                    hash_alg: SignHash::Any,
+                }
+                | AsymmetricSignature::RsaPkcs1v15Sign {
+                    hash_alg: SignHash::Specific(Hash::Sha256),
+                },
+                _,
+            ) => Some(("RS256", Hash::Sha256)),
+            (
+                AsymmetricSignature::RsaPss {
+                    hash_alg: SignHash::Any,
+                }
+                | AsymmetricSignature::RsaPss {
+                    hash_alg: SignHash::Specific(Hash::Sha256),
+                },
+                _,
+            ) => Some(("PS256", Hash::Sha256)),
+            (
+                AsymmetricSignature::Ecdsa { hash_alg }
+                | AsymmetricSignature::DeterministicEcdsa { hash_alg },
+                256,
+            ) if secp_r1
+                && (hash_alg == SignHash::Any || hash_alg == SignHash::Specific(Hash::Sha256)) =>
+            {
+                Some(("ES256", Hash::Sha256))
+            }
+            (
+                AsymmetricSignature::Ecdsa { hash_alg }
+                | AsymmetricSignature::DeterministicEcdsa { hash_alg },
+                384,
+            ) if secp_r1
+                && (hash_alg == SignHash::Any || hash_alg == SignHash::Specific(Hash::Sha384)) =>
+            {
+                Some(("ES384", Hash::Sha384))
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+
+    jose_algorithm.ok_or_else(|| {
+        ToolErrorKind::KeyAlgorithmMismatch {
+            key_name: key_name.to_string(),
+            expected: "an algorithm matching RS256, PS256, ES256 or ES384".to_string(),
+            actual: format!(
+                "{:?} ({:?}, {} bits)",
+                attributes.policy.permitted_algorithms, attributes.key_type, attributes.bits
+            ),
+        }
+        .into()
+    })
+}
@@ -0,0 +1,337 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Runs an SSH agent serving the Parsec keys of the application.
+//!
+//! The agent implements the subset of the SSH agent protocol needed to authenticate with keys
+//! that never leave Parsec: listing identities and signing requests. Keys are RSA PKCS#1 v1.5
+//! keys allowing SHA-512, which OpenSSH asks for, or raw signatures, or ECDSA keys on the NIST
+//! curves.
+
+use crate::error::{Error, Result, ToolErrorKind};
+use crate::ssh::{
+    ecdsa_hash, ecdsa_signature_blob, get_string, get_u32, public_key_blob, put_string,
+};
+use crate::util::{digest_info, hash_data};
+use clap::Parser;
+use log::{info, warn};
+use parsec_client::core::interface::operations::list_keys::KeyInfo;
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricSignature, Hash, SignHash,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{EccFamily, Type};
+use parsec_client::BasicClient;
+use std::fs;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+
+const SSH_AGENT_FAILURE: u8 = 5;
+const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+// Same limit as the OpenSSH agent.
+const MAX_MESSAGE_LEN: usize = 256 * 1024;
+
+/// Runs an SSH agent serving the Parsec keys of the application.
+#[derive(Debug, Parser)]
+pub struct SshAgent {
+    /// Path of the Unix socket to listen on (set SSH_AUTH_SOCK to it)
+    #[structopt(short = 's', long = "socket")]
+    socket: PathBuf,
+
+    /// Only serve the given key (can be repeated). All the usable keys are served by default.
+    #[structopt(short = 'k', long = "key-name")]
+    key_names: Vec<String>,
+}
+
+/// A Parsec key usable through the agent.
+struct Identity {
+    name: String,
+    key_type: String,
+    blob: Vec<u8>,
+    bits: usize,
+    policy: AsymmetricSignature,
+}
+
+impl SshAgent {
+    /// Runs the SSH agent until it is killed.
+    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+        if let Ok(metadata) = fs::symlink_metadata(&self.socket) {
+            if !metadata.file_type().is_socket() {
+                return Err(ToolErrorKind::InvalidData(format!(
+                    "{} already exists and is not a socket",
+                    self.socket.display()
+                ))
+                .into());
+            }
+            if UnixStream::connect(&self.socket).is_ok() {
+                return Err(ToolErrorKind::InvalidData(format!(
+                    "an SSH agent is already listening on {}",
+                    self.socket.display()
+                ))
+                .into());
+            }
+            // Left behind by a previous agent.
+            fs::remove_file(&self.socket)?;
+        }
+        // Only the user can connect to the agent: the socket is created with mode 0600.
+        let umask = unsafe { libc::umask(0o177) };
+        let listener = UnixListener::bind(&self.socket);
+        let _ = unsafe { libc::umask(umask) };
+        let listener = listener?;
+
+        println!(
+            "SSH_AUTH_SOCK={}; export SSH_AUTH_SOCK;",
+            self.socket.display()
+        );
+        info!("Listening for SSH agent requests...");
+
+        // Requests are served one connection at a time. The identities are listed again when a
+        // client asks for them, and reused for the sign requests.
+        let mut identities = None;
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if let Err(e) = self.serve(&basic_client, &mut identities, stream) {
+                        warn!("Closing SSH agent connection: {}", e);
+                    }
+                }
+                Err(e) => warn!("Failed to accept SSH agent connection: {}", e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn serve(
+        &self,
+        basic_client: &BasicClient,
+        identities: &mut Option<Vec<Identity>>,
+        mut stream: UnixStream,
+    ) -> Result<()> {
+        loop {
+            let mut len = [0; 4];
+            if let Err(e) = stream.read_exact(&mut len) {
+                if e.kind() == ErrorKind::UnexpectedEof {
+                    return Ok(());
+                }
+                return Err(e.into());
+            }
+            let len = u32::from_be_bytes(len) as usize;
+            if len == 0 || len > MAX_MESSAGE_LEN {
+                return Err(ToolErrorKind::InvalidData(format!(
+                    "invalid SSH agent message length ({} bytes)",
+                    len
+                ))
+                .into());
+            }
+            let mut message = vec![0; len];
+            stream.read_exact(&mut message)?;
+
+            let response = match self.handle(basic_client, identities, &message) {
+                Ok(response) => response,
+                Err(e) => {
+                    warn!("SSH agent request failed: {}", e);
+                    vec![SSH_AGENT_FAILURE]
+                }
+            };
+
+            let mut reply = Vec::with_capacity(response.len() + 4);
+            put_string(&mut reply, &response);
+            stream.write_all(&reply)?;
+        }
+    }
+
+    fn handle(
+        &self,
+        basic_client: &BasicClient,
+        identities: &mut Option<Vec<Identity>>,
+        message: &[u8],
+    ) -> Result<Vec<u8>> {
+        match message[0] {
+            SSH_AGENTC_REQUEST_IDENTITIES => {
+                let identities = identities.insert(self.identities(basic_client)?);
+                let mut response = vec![SSH_AGENT_IDENTITIES_ANSWER];
+                response.extend_from_slice(&(identities.len() as u32).to_be_bytes());
+                for identity in identities.iter() {
+                    put_string(&mut response, &identity.blob);
+                    put_string(&mut response, identity.name.as_bytes());
+                }
+                Ok(response)
+            }
+            SSH_AGENTC_SIGN_REQUEST => {
+                let mut request = &message[1..];
+                let blob = get_string(&mut request)?;
+                let data = get_string(&mut request)?;
+                let flags = get_u32(&mut request)?;
+
+                if identities.is_none() {
+                    *identities = Some(self.identities(basic_client)?);
+                }
+                let identity = match identities
+                    .iter()
+                    .flatten()
+                    .find(|identity| identity.blob == blob)
+                {
+                    Some(identity) => identity,
+                    None => {
+                        return Err(ToolErrorKind::InvalidData(
+                            "the SSH agent was asked to sign with an unknown key".to_string(),
+                        )
+                        .into());
+                    }
+                };
+
+                let mut response = vec![SSH_AGENT_SIGN_RESPONSE];
+                put_string(&mut response, &sign(basic_client, identity, data, flags)?);
+                Ok(response)
+            }
+            other => {
+                info!("Unsupported SSH agent request ({})", other);
+                Ok(vec![SSH_AGENT_FAILURE])
+            }
+        }
+    }
+
+    /// Lists the keys of the implicit provider that can be used through the agent.
+    fn identities(&self, basic_client: &BasicClient) -> Result<Vec<Identity>> {
+        let mut identities = Vec::new();
+        for key in basic_client.list_keys()? {
+            if key.provider_id != basic_client.implicit_provider()
+                || (!self.key_names.is_empty() && !self.key_names.contains(&key.name))
+            {
+                continue;
+            }
+            let policy = match ssh_policy(&key) {
+                Some(policy) => policy,
+                None => continue,
+            };
+            let (key_type, blob) = match basic_client
+                .psa_export_public_key(&key.name)
+                .map_err(Error::from)
+                .and_then(|public_key| public_key_blob(&key.name, &key.attributes, &public_key))
+            {
+                Ok(identity) => identity,
+                Err(e) => {
+                    warn!("Not serving key \"{}\": {}", key.name, e);
+                    continue;
+                }
+            };
+            identities.push(Identity {
+                name: key.name,
+                key_type,
+                blob,
+                bits: key.attributes.bits,
+                policy,
+            });
+        }
+        Ok(identities)
+    }
+}
+
+/// Returns the signing policy of the key if it can produce SSH signatures.
+fn ssh_policy(key: &KeyInfo) -> Option<AsymmetricSignature> {
+    let policy = match key.attributes.policy.permitted_algorithms {
+        Algorithm::AsymmetricSignature(policy) => policy,
+        _ => return None,
+    };
+    match (key.attributes.key_type, policy) {
+        (
+            Type::RsaKeyPair,
+            AsymmetricSignature::RsaPkcs1v15Sign {
+                hash_alg: SignHash::Any,
+            }
+            | AsymmetricSignature::RsaPkcs1v15Sign {
+                hash_alg: SignHash::Specific(Hash::Sha512),
+            }
+            | AsymmetricSignature::RsaPkcs1v15SignRaw,
+        ) => Some(policy),
+        (
+            Type::EccKeyPair {
+                curve_family: EccFamily::SecpR1,
+            },
+            AsymmetricSignature::Ecdsa { hash_alg }
+            | AsymmetricSignature::DeterministicEcdsa { hash_alg },
+        ) => match ecdsa_hash(key.attributes.bits) {
+            Some(hash) if hash_alg == SignHash::Any || hash_alg == SignHash::Specific(hash) => {
+                Some(policy)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Signs the data of a sign request, returning the SSH signature blob.
+fn sign(
+    basic_client: &BasicClient,
+    identity: &Identity,
+    data: &[u8],
+    flags: u32,
+) -> Result<Vec<u8>> {
+    if identity.policy.is_rsa_alg() {
+        let (signature_type, hash) = if flags & SSH_AGENT_RSA_SHA2_512 != 0 {
+            ("rsa-sha2-512", Hash::Sha512)
+        } else if flags & SSH_AGENT_RSA_SHA2_256 != 0 {
+            ("rsa-sha2-256", Hash::Sha256)
+        } else {
+            return Err(ToolErrorKind::UnsupportedOperation(
+                "SHA-1 RSA signatures (\"ssh-rsa\") are not supported".to_string(),
+            )
+            .into());
+        };
+        let digest = hash_data(data, hash)?;
+        let (alg, digest) = if identity.policy == AsymmetricSignature::RsaPkcs1v15SignRaw {
+            // Raw signatures are made over the DigestInfo of the hash.
+            (identity.policy, digest_info(hash, &digest)?)
+        } else {
+            let alg = AsymmetricSignature::RsaPkcs1v15Sign {
+                hash_alg: hash.into(),
+            };
+            if !identity.policy.is_alg_permitted(alg) {
+                return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                    key_name: identity.name.clone(),
+                    expected: format!("{:?}", alg),
+                    actual: format!("{:?}", identity.policy),
+                }
+                .into());
+            }
+            (alg, digest)
+        };
+        info!("Signing SSH data with {:?}...", alg);
+        let signature = basic_client.psa_sign_hash(&identity.name, &digest, alg)?;
+
+        let mut blob = Vec::new();
+        put_string(&mut blob, signature_type.as_bytes());
+        put_string(&mut blob, &signature);
+        Ok(blob)
+    } else {
+        // ssh_policy only accepts curves with a known hash.
+        let hash =
+            ecdsa_hash(identity.bits).ok_or_else(|| ToolErrorKind::UnsupportedKeyAlgorithm {
+                key_name: identity.name.clone(),
+                algorithm: format!("ECDSA with {} bits", identity.bits),
+                operation: "SSH signatures".to_string(),
+            })?;
+        let alg = match identity.policy {
+            AsymmetricSignature::DeterministicEcdsa { .. } => {
+                AsymmetricSignature::DeterministicEcdsa {
+                    hash_alg: hash.into(),
+                }
+            }
+            _ => AsymmetricSignature::Ecdsa {
+                hash_alg: hash.into(),
+            },
+        };
+        info!("Signing SSH data with {:?}...", alg);
+        let signature = basic_client.psa_sign_hash(&identity.name, &hash_data(data, hash)?, alg)?;
+        Ok(ecdsa_signature_blob(&identity.key_type, &signature))
+    }
+}
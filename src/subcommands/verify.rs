@@ -0,0 +1,205 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verifies the signature of some data, as produced by the `sign` subcommand.
+//!
+//! Will use the algorithm set to the key's policy during creation. COSE signatures hold their
+//! payload: the input data is then optional, and compared with the payload if given.
+
+use crate::cose::{self, CoseSign1};
+use crate::error::{Result, ToolErrorKind};
+use crate::ssh::{
+    ecdsa_signature_to_der, get_string, public_key_blob, signature_scheme, SshSignature,
+};
+use crate::util::{
+    check_policy_hash, read_input, verify_message_with_policy, HashAlgorithm, Input,
+    SignatureEncoding, SignatureFormat,
+};
+use clap::Parser;
+use parsec_client::core::interface::operations::psa_algorithm::Hash;
+use parsec_client::BasicClient;
+use std::fs;
+use std::path::PathBuf;
+
+/// Verifies the signature of some data.
+#[derive(Debug, Parser)]
+pub struct Verify {
+    #[structopt(short = 'k', long = "key-name")]
+    key_name: String,
+
+    /// String of UTF-8 text
+    input_data: Option<String>,
+
+    /// File containing the signed data, instead of a string
+    #[structopt(short = 'i', long = "input-file", conflicts_with = "input_data")]
+    input_file: Option<PathBuf>,
+
+    /// File containing the signature
+    #[structopt(short = 's', long = "signature")]
+    signature: PathBuf,
+
+    /// Format of the signature
+    #[structopt(short = 'f', long = "format", default_value = "base64")]
+    format: SignatureFormat,
+
+    /// Encoding of ECDSA signatures in the base64 format: DER (default) or raw r||s
+    #[structopt(long = "sig-format")]
+    sig_format: Option<SignatureEncoding>,
+
+    /// Hash algorithm of the data for keys allowing any hash algorithm or raw signatures (SHA-256
+    /// by default for those). Only accepted for base64 signatures.
+    #[structopt(long = "hash")]
+    hash: Option<HashAlgorithm>,
+
+    /// Expected namespace of an SSH signature
+    #[structopt(short = 'n', long = "namespace", required_if_eq("format", "sshsig"))]
+    namespace: Option<String>,
+}
+
+impl Verify {
+    /// Verifies the signature of some data.
+    pub fn run(&self, basic_client: BasicClient) -> Result<()> {
+        if self.sig_format.is_some() && self.format != SignatureFormat::Base64 {
+            return Err(ToolErrorKind::UnsupportedOperation(
+                "the signature encoding can only be chosen for base64 signatures".to_string(),
+            )
+            .into());
+        }
+        if self.hash.is_some() && self.format != SignatureFormat::Base64 {
+            return Err(ToolErrorKind::UnsupportedOperation(
+                "the hash algorithm can only be chosen for base64 signatures".to_string(),
+            )
+            .into());
+        }
+        if self.format == SignatureFormat::Cose {
+            let signature = CoseSign1::decode(&fs::read(&self.signature)?)?;
+            self.verify_cose(&basic_client, &signature)?;
+            println!("Signature verified");
+            return Ok(());
+        }
+
+        let input = Input::open(&self.input_data, &self.input_file)?;
+
+        match self.format {
+            SignatureFormat::Base64 => {
+                let signature = base64::decode(fs::read_to_string(&self.signature)?.trim())?;
+                if let Some(hash) = self.hash {
+                    check_policy_hash(&basic_client, &self.key_name, hash.into())?;
+                }
+                verify_message_with_policy(
+                    &basic_client,
+                    &self.key_name,
+                    input,
+                    &signature,
+                    Some(self.hash.map_or(Hash::Sha256, Hash::from)),
+                    self.sig_format.unwrap_or(SignatureEncoding::Der),
+                )?;
+            }
+            SignatureFormat::Sshsig => {
+                let signature = SshSignature::from_armored(&fs::read_to_string(&self.signature)?)?;
+                self.verify_sshsig(&basic_client, input, &signature)?;
+            }
+            SignatureFormat::Cms | SignatureFormat::CmsDer => {
+                return Err(ToolErrorKind::UnsupportedOperation(
+                    "CMS signatures can be verified with \"openssl cms -verify\"".to_string(),
+                )
+                .into());
+            }
+            SignatureFormat::Cose => unreachable!(),
+        }
+
+        println!("Signature verified");
+
+        Ok(())
+    }
+
+    fn verify_sshsig(
+        &self,
+        basic_client: &BasicClient,
+        input: Input<'_>,
+        signature: &SshSignature,
+    ) -> Result<()> {
+        if self.namespace.as_ref() != Some(&signature.namespace) {
+            return Err(ToolErrorKind::InvalidSignature(format!(
+                "the signature was made for the \"{}\" namespace",
+                signature.namespace
+            ))
+            .into());
+        }
+
+        let attributes = basic_client.key_attributes(&self.key_name)?;
+        let public_key = basic_client.psa_export_public_key(&self.key_name)?;
+        let (_, public_key) = public_key_blob(&self.key_name, &attributes, &public_key)?;
+        if public_key != signature.public_key {
+            return Err(ToolErrorKind::InvalidSignature(format!(
+                "the signature was not made with key \"{}\"",
+                self.key_name
+            ))
+            .into());
+        }
+
+        let mut blob = signature.signature.as_slice();
+        let signature_type = String::from_utf8_lossy(get_string(&mut blob)?).into_owned();
+        let raw_signature = get_string(&mut blob)?;
+        let (hash, raw_signature) = match signature_type.as_str() {
+            "rsa-sha2-256" => (Hash::Sha256, raw_signature.to_vec()),
+            "rsa-sha2-512" => (Hash::Sha512, raw_signature.to_vec()),
+            _ => {
+                let (hash, key_signature_type) = signature_scheme(&self.key_name, &attributes)?;
+                if signature_type != key_signature_type {
+                    return Err(ToolErrorKind::InvalidSignature(format!(
+                        "the {} signature was not made with key \"{}\" ({})",
+                        signature_type, self.key_name, key_signature_type
+                    ))
+                    .into());
+                }
+                (hash, ecdsa_signature_to_der(raw_signature)?)
+            }
+        };
+
+        let signed_data =
+            SshSignature::signed_data(&signature.namespace, &signature.hash_algorithm, input)?;
+        verify_message_with_policy(
+            basic_client,
+            &self.key_name,
+            &signed_data,
+            &raw_signature,
+            Some(hash),
+            SignatureEncoding::Der,
+        )
+    }
+
+    fn verify_cose(&self, basic_client: &BasicClient, signature: &CoseSign1) -> Result<()> {
+        if self.input_data.is_some() || self.input_file.is_some() {
+            let input = read_input(&self.input_data, &self.input_file)?;
+            if input != signature.payload {
+                return Err(ToolErrorKind::InvalidSignature(
+                    "the input data is not the payload of the COSE signature".to_string(),
+                )
+                .into());
+            }
+        }
+
+        let (alg, hash) = cose::algorithm(
+            &self.key_name,
+            &basic_client.key_attributes(&self.key_name)?,
+        )?;
+        let signature_alg = signature.algorithm()?;
+        if signature_alg != alg {
+            return Err(ToolErrorKind::InvalidSignature(format!(
+                "the signature uses COSE algorithm {} but key \"{}\" uses {}",
+                signature_alg, self.key_name, alg
+            ))
+            .into());
+        }
+
+        verify_message_with_policy(
+            basic_client,
+            &self.key_name,
+            &signature.to_be_signed(),
+            &signature.signature,
+            Some(hash),
+            SignatureEncoding::Raw,
+        )
+    }
+}
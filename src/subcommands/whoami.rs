@@ -0,0 +1,141 @@
+// Copyright 2024 Contributors to the Parsec project.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Shows the identity the tool authenticates with, and the service it talks to.
+
+use crate::cli::OutputFormat;
+use crate::error::Result;
+use clap::Parser;
+use log::info;
+use parsec_client::auth::Authentication;
+use parsec_client::core::ipc_handler::unix_socket::DEFAULT_SOCKET_PATH;
+use parsec_client::BasicClient;
+use serde_json::{json, Map, Value};
+use std::env;
+
+/// Shows the identity the tool authenticates with, and the service it talks to.
+#[derive(Debug, Parser)]
+pub struct Whoami {}
+
+impl Whoami {
+    /// Shows the service endpoint and wire protocol version, the authenticator used, the identity
+    /// of the application and the implicit provider. JWT-SVIDs are fetched with the given audience.
+    pub fn run(
+        &self,
+        basic_client: BasicClient,
+        format: OutputFormat,
+        spiffe_audience: &str,
+    ) -> Result<()> {
+        // Same default as the client.
+        let endpoint = env::var("PARSEC_SERVICE_ENDPOINT")
+            .unwrap_or_else(|_| format!("unix:{}", DEFAULT_SOCKET_PATH));
+        let (major, minor) = basic_client.ping()?;
+        let auth_data = basic_client.auth_data();
+        let identity = identity(&auth_data, spiffe_audience)?;
+        let provider = basic_client.implicit_provider();
+
+        match format {
+            OutputFormat::Text => {
+                info!("Session information:");
+                println!("Endpoint: {}", endpoint);
+                println!("Wire protocol version: {}.{}", major, minor);
+                println!("Authenticator: {}", auth_data.auth_type());
+                if let Some((label, identity)) = &identity {
+                    println!("{}: {}", label.text(), identity);
+                }
+                println!(
+                    "Implicit provider: 0x{:02x} ({})",
+                    provider as u32, provider
+                );
+            }
+            OutputFormat::Json => {
+                let mut info = Map::new();
+                let _ = info.insert(String::from("endpoint"), json!(endpoint));
+                let _ = info.insert(
+                    String::from("wire_protocol_version"),
+                    json!(format!("{}.{}", major, minor)),
+                );
+                let _ = info.insert(
+                    String::from("authenticator"),
+                    json!({
+                        "id": auth_data.auth_type() as u8,
+                        "name": auth_data.auth_type().to_string(),
+                    }),
+                );
+                if let Some((label, identity)) = identity {
+                    let _ = info.insert(String::from(label.key()), json!(identity));
+                }
+                let _ = info.insert(
+                    String::from("implicit_provider"),
+                    json!({
+                        "id": provider as u8,
+                        "name": provider.to_string(),
+                    }),
+                );
+                println!("{}", Value::from(info));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Kind of application identity, depending on the authenticator.
+#[derive(Clone, Copy, Debug)]
+enum IdentityLabel {
+    AppName,
+    Uid,
+    #[cfg_attr(not(feature = "spiffe-auth"), allow(dead_code))]
+    SpiffeId,
+}
+
+impl IdentityLabel {
+    fn text(self) -> &'static str {
+        match self {
+            IdentityLabel::AppName => "Application name",
+            IdentityLabel::Uid => "User ID",
+            IdentityLabel::SpiffeId => "SPIFFE ID",
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            IdentityLabel::AppName => "app_name",
+            IdentityLabel::Uid => "uid",
+            IdentityLabel::SpiffeId => "spiffe_id",
+        }
+    }
+}
+
+/// Returns the identity the service sees, if any.
+#[cfg_attr(not(feature = "spiffe-auth"), allow(unused_variables))]
+fn identity(
+    auth_data: &Authentication,
+    spiffe_audience: &str,
+) -> Result<Option<(IdentityLabel, String)>> {
+    match auth_data {
+        Authentication::None => Ok(None),
+        Authentication::Direct(app_name) => Ok(Some((IdentityLabel::AppName, app_name.clone()))),
+        // Same as the client.
+        Authentication::UnixPeerCredentials => Ok(Some((
+            IdentityLabel::Uid,
+            unsafe { libc::getuid() }.to_string(),
+        ))),
+        #[cfg(feature = "spiffe-auth")]
+        Authentication::JwtSvid => Ok(Some((IdentityLabel::SpiffeId, spiffe_id(spiffe_audience)?))),
+    }
+}
+
+/// Returns the SPIFFE ID of the JWT-SVID fetched from the Workload API.
+#[cfg(feature = "spiffe-auth")]
+fn spiffe_id(audience: &str) -> Result<String> {
+    use crate::error::ToolErrorKind;
+    use spiffe::workload_api::client::WorkloadApiClient;
+
+    let svid = WorkloadApiClient::default()
+        .and_then(|client| client.fetch_jwt_svid(&[audience], None))
+        .map_err(|e| ToolErrorKind::JwtSvidUnavailable {
+            audience: audience.to_string(),
+            reason: e.to_string(),
+        })?;
+    Ok(svid.spiffe_id().to_string())
+}
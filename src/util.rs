@@ -4,89 +4,623 @@
 //! Utility code that is shared by multiple subcommands;
 
 use crate::error::{Result, ToolErrorKind};
-use log::{error, info};
-use parsec_client::core::interface::operations::psa_algorithm::{Algorithm, Hash, SignHash};
+use clap::ValueEnum;
+use is_terminal::IsTerminal;
+use log::{info, warn};
+use parsec_client::core::interface::operations::psa_algorithm::{
+    Algorithm, AsymmetricEncryption, AsymmetricSignature, Hash, SignHash,
+};
+use parsec_client::core::interface::operations::psa_key_attributes::{Attributes, Lifetime};
 use parsec_client::BasicClient;
 use picky_asn1::wrapper::IntegerAsn1;
+use picky_asn1_x509::{PublicKey, RsaPublicKey, SubjectPublicKeyInfo};
 use serde::{Deserialize, Serialize};
 use sha2::digest::{Digest, DynDigest};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
 
+// Size of the chunks in which data is read to be hashed.
+const HASH_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Hashing algorithms that can be selected on the command-line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum HashAlgorithm {
+    /// SHA-224
+    Sha224,
+    /// SHA-256
+    Sha256,
+    /// SHA-384
+    Sha384,
+    /// SHA-512
+    Sha512,
+}
+
+impl From<HashAlgorithm> for Hash {
+    fn from(hash: HashAlgorithm) -> Self {
+        match hash {
+            HashAlgorithm::Sha224 => Hash::Sha224,
+            HashAlgorithm::Sha256 => Hash::Sha256,
+            HashAlgorithm::Sha384 => Hash::Sha384,
+            HashAlgorithm::Sha512 => Hash::Sha512,
+        }
+    }
+}
+
+/// Formats of the signatures produced by the tool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SignatureFormat {
+    /// Base64-encoded signature
+    Base64,
+    /// Armored OpenSSH signature (as produced by `ssh-keygen -Y sign`)
+    Sshsig,
+    /// PEM-encoded CMS SignedData with detached content
+    Cms,
+    /// DER-encoded CMS SignedData with detached content
+    CmsDer,
+    /// COSE_Sign1 structure (CBOR) with the content attached
+    Cose,
+}
+
+/// Returns the data given on the command-line, either as a string or as the path of a file.
+pub fn read_input(input_data: &Option<String>, input_file: &Option<PathBuf>) -> Result<Vec<u8>> {
+    match (input_data, input_file) {
+        (Some(input_data), None) => Ok(input_data.as_bytes().to_vec()),
+        (None, Some(input_file)) => Ok(fs::read(input_file)?),
+        _ => Err(ToolErrorKind::MissingInput(
+            "either input data or an input file must be given".to_string(),
+        )
+        .into()),
+    }
+}
+
+/// Data to hash, read in chunks so that large files never need to fit in memory.
+pub struct Input<'a> {
+    reader: Box<dyn Read + 'a>,
+    len: u64,
+    progress: bool,
+}
+
+impl fmt::Debug for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Input")
+            .field("len", &self.len)
+            .field("progress", &self.progress)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Input<'static> {
+    /// Opens the data given on the command-line, either as a string or as the path of a file.
+    ///
+    /// The progress of hashing a file is shown if the standard error is a terminal.
+    pub fn open(input_data: &Option<String>, input_file: &Option<PathBuf>) -> Result<Self> {
+        match (input_data, input_file) {
+            (Some(input_data), None) => Ok(Input {
+                len: input_data.len() as u64,
+                reader: Box::new(io::Cursor::new(input_data.clone().into_bytes())),
+                progress: false,
+            }),
+            (None, Some(input_file)) => {
+                let file = File::open(input_file)?;
+                Ok(Input {
+                    len: file.metadata()?.len(),
+                    reader: Box::new(file),
+                    progress: io::stderr().is_terminal(),
+                })
+            }
+            _ => Err(ToolErrorKind::MissingInput(
+                "either input data or an input file must be given".to_string(),
+            )
+            .into()),
+        }
+    }
+}
+
+impl<'a, T: AsRef<[u8]> + ?Sized> From<&'a T> for Input<'a> {
+    fn from(data: &'a T) -> Self {
+        let data = data.as_ref();
+        Input {
+            len: data.len() as u64,
+            reader: Box::new(data),
+            progress: false,
+        }
+    }
+}
+
+impl Input<'_> {
+    /// Hashes the data with the given algorithm, locally.
+    pub fn hash(mut self, alg: Hash) -> Result<Vec<u8>> {
+        let mut hasher: Box<dyn DynDigest> = match alg {
+            Hash::Sha224 => Box::from(sha2::Sha224::new()),
+            Hash::Sha256 => Box::from(sha2::Sha256::new()),
+            Hash::Sha384 => Box::from(sha2::Sha384::new()),
+            Hash::Sha512 => Box::from(sha2::Sha512::new()),
+            _ => {
+                return Err(ToolErrorKind::UnsupportedOperation(format!(
+                    "hashing algorithm {:?} is not supported",
+                    alg
+                ))
+                .into());
+            }
+        };
+        info!("Hashing data with {:?}...", alg);
+
+        let mut chunk = vec![0; HASH_CHUNK_SIZE];
+        let mut hashed = 0;
+        let mut shown_percent = None;
+        loop {
+            let read = match self.reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            hasher.update(&chunk[..read]);
+            hashed += read as u64;
+
+            if self.progress {
+                let percent = (hashed * 100).checked_div(self.len).unwrap_or(100).min(100);
+                if shown_percent != Some(percent) {
+                    eprint!("\rHashing... {}%", percent);
+                    shown_percent = Some(percent);
+                }
+            }
+        }
+        if shown_percent.is_some() {
+            eprintln!();
+        }
+
+        Ok(hasher.finalize().to_vec())
+    }
+}
+
+/// Decodes a hexadecimal string, such as a digest given on the command-line.
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.trim();
+    let invalid =
+        || ToolErrorKind::InvalidData(format!("\"{}\" is not a valid hexadecimal string", hex));
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        return Err(invalid().into());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| invalid().into()))
+        .collect()
+}
+
+/// Returns the RSA-OAEP label given on the command-line, either as a string, in hexadecimal or as
+/// the path of a file.
+pub fn read_label(
+    label: &Option<String>,
+    label_hex: &Option<String>,
+    label_file: &Option<PathBuf>,
+) -> Result<Option<Vec<u8>>> {
+    match (label, label_hex, label_file) {
+        (None, None, None) => Ok(None),
+        (Some(label), None, None) => Ok(Some(label.as_bytes().to_vec())),
+        (None, Some(label_hex), None) => Ok(Some(decode_hex(label_hex)?)),
+        (None, None, Some(label_file)) => Ok(Some(fs::read(label_file)?)),
+        _ => Err(ToolErrorKind::InvalidData("only one label can be given".to_string()).into()),
+    }
+}
+
+/// Checks that a label is only given for RSA-OAEP, the only algorithm using one.
+pub fn check_label(key_name: &str, alg: Algorithm, label: &Option<Vec<u8>>) -> Result<()> {
+    match alg {
+        Algorithm::AsymmetricEncryption(AsymmetricEncryption::RsaOaep { .. }) => Ok(()),
+        _ if label.is_none() => Ok(()),
+        other => Err(ToolErrorKind::KeyAlgorithmMismatch {
+            key_name: key_name.to_string(),
+            expected: "RSA-OAEP to use a label".to_string(),
+            actual: format!("{:?}", other),
+        }
+        .into()),
+    }
+}
+
+/// Prints data in a PEM block with the given tag.
+pub fn print_pem(tag: &str, contents: Vec<u8>) {
+    let pem_encoded = pem::encode_config(
+        &pem::Pem {
+            tag: String::from(tag),
+            contents,
+        },
+        pem::EncodeConfig {
+            line_ending: pem::LineEnding::LF,
+        },
+    );
+
+    print!("{}", pem_encoded);
+}
+
+/// Writes binary data to the standard output.
+pub fn write_binary(data: &[u8]) -> Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(data)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Reads a file containing a single PEM block or DER data, and returns the DER data.
+pub fn read_der(path: &Path) -> Result<Vec<u8>> {
+    let content = fs::read(path)?;
+    if content.starts_with(b"-----BEGIN") {
+        let pem = pem::parse(&content).map_err(|_| {
+            ToolErrorKind::InvalidData(format!("could not parse the PEM file {}", path.display()))
+        })?;
+        info!("Found PEM block with tag \"{}\"", pem.tag);
+        Ok(pem.contents)
+    } else {
+        Ok(content)
+    }
+}
+
+/// Checks if a SubjectPublicKeyInfo contains the public part of a key exported by Parsec.
+pub fn public_key_matches(spki: &SubjectPublicKeyInfo, parsec_public_key: &[u8]) -> bool {
+    match &spki.subject_public_key {
+        PublicKey::Rsa(rsa) => {
+            match picky_asn1_der::from_bytes::<RsaPublicKey>(parsec_public_key) {
+                Ok(parsec_rsa) => {
+                    parsec_rsa.modulus.as_unsigned_bytes_be()
+                        == rsa.0.modulus.as_unsigned_bytes_be()
+                        && parsec_rsa.public_exponent.as_unsigned_bytes_be()
+                            == rsa.0.public_exponent.as_unsigned_bytes_be()
+                }
+                Err(_) => {
+                    warn!("The Parsec key is not an RSA key");
+                    false
+                }
+            }
+        }
+        PublicKey::Ec(point) | PublicKey::Ed(point) => point.0.payload_view() == parsec_public_key,
+    }
+}
+
+/// Encodings of the ECDSA signatures produced by the tool. RSA signatures only have one encoding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SignatureEncoding {
+    /// ASN.1 `Ecdsa-Sig-Value` structure (RFC 3279)
+    Der,
+    /// Fixed-width concatenation of r and s, as produced by Parsec
+    Raw,
+}
+
+/// Encodes data in base64url without padding (RFC 7515), as used by JOSE.
+pub fn base64_url(data: &[u8]) -> String {
+    base64::encode_config(data, base64::URL_SAFE_NO_PAD)
+}
+
+/// DER encoding of an ECDSA signature.
 #[derive(Serialize, Deserialize)]
-struct EccSignature {
-    r: IntegerAsn1,
-    s: IntegerAsn1,
+pub(crate) struct EccSignature {
+    pub(crate) r: IntegerAsn1,
+    pub(crate) s: IntegerAsn1,
 }
 
 /// Signs a given message using the hashing and signing policy that was associated with the given key when
 /// it was created.
 ///
 /// If the signing key allows for the use of any hashing algorithm, then a default hash can optionally be passed
-/// by the caller, and this hash will be used (otherwise the function will fail).
-pub fn sign_message_with_policy(
+/// by the caller, and this hash will be used (otherwise the function will fail). Keys restricted to
+/// raw signatures (`RsaPkcs1v15SignRaw` and `EcdsaAny`) also need this hash, the message is hashed with
+/// it before being signed.
+///
+/// ECDSA signatures are returned with the given encoding.
+pub fn sign_message_with_policy<'a>(
     basic_client: &BasicClient,
     key_name: &str,
-    msg: &[u8],
+    msg: impl Into<Input<'a>>,
     default_hash: Option<Hash>,
+    encoding: SignatureEncoding,
 ) -> Result<Vec<u8>> {
-    let alg = basic_client
-        .key_attributes(key_name)?
-        .policy
-        .permitted_algorithms;
-
-    let signature = match alg {
-        Algorithm::AsymmetricSignature(alg) => {
-            let hash = match alg.hash() {
-                Some(SignHash::Specific(hash)) => hash_data(msg, hash)?,
-                Some(SignHash::Any) => {
-                    if let Some(hash) = default_hash {
-                        hash_data(msg, hash)?
-                    } else {
-                        error!("Signing key allows any hashing algorithm, but no default was specified.");
-                        return Err(ToolErrorKind::NotSupported.into());
-                    }
-                }
-                _ => {
-                    error!("Asymmetric signing algorithm ({:?}) is not supported", alg);
-                    return Err(ToolErrorKind::NotSupported.into());
-                }
-            };
-            info!("Signing data with {:?}...", alg);
-            let mut sig = basic_client.psa_sign_hash(key_name, &hash, alg)?;
-            if alg.is_ecc_alg() {
-                let s = IntegerAsn1::from_bytes_be_unsigned(sig.split_off(sig.len() / 2));
-                sig = picky_asn1_der::to_vec(&EccSignature {
-                    r: IntegerAsn1::from_bytes_be_unsigned(sig),
-                    s,
-                })
-                .unwrap();
-            }
+    let policy = signature_policy(basic_client, key_name)?;
+    let hash = policy_hash(key_name, policy, default_hash)?;
+    let digest = msg.into().hash(hash)?;
+    sign_digest(
+        basic_client,
+        key_name,
+        policy,
+        Some(hash),
+        &digest,
+        encoding,
+    )
+}
 
-            sig
+/// Signs a digest computed beforehand, using the signing policy that was associated with the given
+/// key when it was created.
+///
+/// The hash algorithm that computed the digest must be given if the key allows for the use of any hashing
+/// algorithm, and must match the policy of keys restricted to one. It is optional for keys
+/// restricted to raw signatures: without it, the digest is signed as is (for `RsaPkcs1v15SignRaw`
+/// keys, the caller then provides the DigestInfo structure).
+///
+/// ECDSA signatures are returned with the given encoding.
+pub fn sign_hash_with_policy(
+    basic_client: &BasicClient,
+    key_name: &str,
+    digest: &[u8],
+    hash: Option<Hash>,
+    encoding: SignatureEncoding,
+) -> Result<Vec<u8>> {
+    let policy = signature_policy(basic_client, key_name)?;
+    check_hash(key_name, policy, hash)?;
+    let hash = match (policy.hash(), hash) {
+        (Some(SignHash::Specific(policy_hash)), _) => Some(policy_hash),
+        (Some(SignHash::Any), None) => {
+            return Err(ToolErrorKind::MissingInput(format!(
+                "key \"{}\" allows any hashing algorithm, the hash of the digest must be given",
+                key_name
+            ))
+            .into());
         }
+        (_, hash) => hash,
+    };
+    if let Some(hash) = hash {
+        if digest.len() != hash.hash_length() {
+            return Err(ToolErrorKind::InvalidData(format!(
+                "the digest is {} bytes long, but {:?} digests are {} bytes long",
+                digest.len(),
+                hash,
+                hash.hash_length()
+            ))
+            .into());
+        }
+    }
+    sign_digest(basic_client, key_name, policy, hash, digest, encoding)
+}
+
+/// Verifies the signature of a message using the hashing and signing policy that was associated
+/// with the given key when it was created. This is the counterpart of `sign_message_with_policy`:
+/// ECDSA signatures are expected with the given encoding.
+pub fn verify_message_with_policy<'a>(
+    basic_client: &BasicClient,
+    key_name: &str,
+    msg: impl Into<Input<'a>>,
+    signature: &[u8],
+    default_hash: Option<Hash>,
+    encoding: SignatureEncoding,
+) -> Result<()> {
+    let attributes = basic_client.key_attributes(key_name)?;
+    let policy = match attributes.policy.permitted_algorithms {
+        Algorithm::AsymmetricSignature(policy) => policy,
         other => {
-            error!(
-                "Key's algorithm is {:?} which can not be used for signing.",
-                other
-            );
-            return Err(ToolErrorKind::WrongKeyAlgorithm.into());
+            return Err(ToolErrorKind::KeyAlgorithmMismatch {
+                key_name: key_name.to_string(),
+                expected: "an asymmetric signature algorithm".to_string(),
+                actual: format!("{:?}", other),
+            }
+            .into());
         }
     };
+    let hash = policy_hash(key_name, policy, default_hash)?;
+    let digest = msg.into().hash(hash)?;
+    let (alg, input) = signing_operation(key_name, policy, Some(hash), &digest)?;
 
-    Ok(signature)
+    let signature = if alg.is_ecc_alg() && encoding == SignatureEncoding::Der {
+        let signature: EccSignature = picky_asn1_der::from_bytes(signature).map_err(|_| {
+            ToolErrorKind::InvalidSignature("could not deserialise the ECDSA signature".to_string())
+        })?;
+        let scalar_len = (attributes.bits + 7) / 8;
+        let mut raw = Vec::with_capacity(2 * scalar_len);
+        for scalar in [&signature.r, &signature.s] {
+            let bytes = scalar.as_unsigned_bytes_be();
+            if bytes.len() > scalar_len {
+                return Err(ToolErrorKind::InvalidSignature(format!(
+                    "the ECDSA signature is too long for key \"{}\"",
+                    key_name
+                ))
+                .into());
+            }
+            raw.resize(raw.len() + scalar_len - bytes.len(), 0);
+            raw.extend_from_slice(bytes);
+        }
+        raw
+    } else {
+        signature.to_vec()
+    };
+    info!("Verifying signature with {:?}...", alg);
+    basic_client.psa_verify_hash(key_name, &input, alg, &signature)?;
+    Ok(())
 }
 
-fn hash_data(data: &[u8], alg: Hash) -> Result<Vec<u8>> {
-    let mut hasher: Box<dyn DynDigest> = match alg {
-        Hash::Sha224 => Box::from(sha2::Sha224::new()),
-        Hash::Sha256 => Box::from(sha2::Sha256::new()),
-        Hash::Sha384 => Box::from(sha2::Sha384::new()),
-        Hash::Sha512 => Box::from(sha2::Sha512::new()),
+/// Returns the DER-encoded DigestInfo structure of a digest, which is what RSA PKCS#1 v1.5
+/// signatures sign (RFC 8017, section 9.2).
+pub fn digest_info(hash: Hash, digest: &[u8]) -> Result<Vec<u8>> {
+    // DER encoding of the DigestInfo up to the digest, from the notes of RFC 8017, section 9.2.
+    let prefix: &[u8] = match hash {
+        Hash::Sha224 => &[
+            0x30, 0x2d, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x04, 0x05, 0x00, 0x04, 0x1c,
+        ],
+        Hash::Sha256 => &[
+            0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x01, 0x05, 0x00, 0x04, 0x20,
+        ],
+        Hash::Sha384 => &[
+            0x30, 0x41, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x02, 0x05, 0x00, 0x04, 0x30,
+        ],
+        Hash::Sha512 => &[
+            0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x03, 0x05, 0x00, 0x04, 0x40,
+        ],
         _ => {
-            error!("Hashing algorithm ({:?}) not supported", alg);
-            return Err(ToolErrorKind::NotSupported.into());
+            return Err(ToolErrorKind::UnsupportedOperation(format!(
+                "hashing algorithm {:?} is not supported",
+                hash
+            ))
+            .into());
         }
     };
-    info!("Hashing data with {:?}...", alg);
-    hasher.update(data);
-    Ok(hasher.finalize().to_vec())
+    let mut digest_info = prefix.to_vec();
+    digest_info.extend_from_slice(digest);
+    Ok(digest_info)
+}
+
+/// Checks that a hash algorithm chosen by the user can be used with the signing policy of the given
+/// key: keys restricted to one hash algorithm cannot be used with another.
+pub fn check_policy_hash(basic_client: &BasicClient, key_name: &str, hash: Hash) -> Result<()> {
+    check_hash(
+        key_name,
+        signature_policy(basic_client, key_name)?,
+        Some(hash),
+    )
+}
+
+fn check_hash(key_name: &str, policy: AsymmetricSignature, hash: Option<Hash>) -> Result<()> {
+    match (policy.hash(), hash) {
+        (Some(SignHash::Specific(policy_hash)), Some(hash)) if hash != policy_hash => {
+            Err(ToolErrorKind::KeyAlgorithmMismatch {
+                key_name: key_name.to_string(),
+                expected: format!("{:?}", hash),
+                actual: format!("{:?}", policy_hash),
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn signature_policy(basic_client: &BasicClient, key_name: &str) -> Result<AsymmetricSignature> {
+    match basic_client
+        .key_attributes(key_name)?
+        .policy
+        .permitted_algorithms
+    {
+        Algorithm::AsymmetricSignature(policy) => Ok(policy),
+        other => Err(ToolErrorKind::KeyAlgorithmMismatch {
+            key_name: key_name.to_string(),
+            expected: "an asymmetric signature algorithm".to_string(),
+            actual: format!("{:?}", other),
+        }
+        .into()),
+    }
+}
+
+/// Returns the hash to use with a signing policy, falling back to the default hash.
+fn policy_hash(
+    key_name: &str,
+    policy: AsymmetricSignature,
+    default_hash: Option<Hash>,
+) -> Result<Hash> {
+    match (policy.hash(), default_hash) {
+        (Some(SignHash::Specific(hash)), _) => Ok(hash),
+        (_, Some(hash)) => Ok(hash),
+        (Some(SignHash::Any), None) => Err(ToolErrorKind::MissingInput(format!(
+            "key \"{}\" allows any hashing algorithm, but no hash was given",
+            key_name
+        ))
+        .into()),
+        (None, None) => Err(ToolErrorKind::MissingInput(format!(
+            "key \"{}\" only allows raw signatures ({:?}), but no hash was given",
+            key_name, policy
+        ))
+        .into()),
+    }
+}
+
+/// Returns the algorithm of a signing operation permitted by the policy, and the data to pass to
+/// Parsec to sign or verify the digest.
+fn signing_operation(
+    key_name: &str,
+    policy: AsymmetricSignature,
+    hash: Option<Hash>,
+    digest: &[u8],
+) -> Result<(AsymmetricSignature, Vec<u8>)> {
+    let alg = match (policy, hash) {
+        (AsymmetricSignature::RsaPkcs1v15Sign { .. }, Some(hash)) => {
+            AsymmetricSignature::RsaPkcs1v15Sign {
+                hash_alg: hash.into(),
+            }
+        }
+        (AsymmetricSignature::RsaPss { .. }, Some(hash)) => AsymmetricSignature::RsaPss {
+            hash_alg: hash.into(),
+        },
+        (AsymmetricSignature::Ecdsa { .. }, Some(hash)) => AsymmetricSignature::Ecdsa {
+            hash_alg: hash.into(),
+        },
+        (AsymmetricSignature::DeterministicEcdsa { .. }, Some(hash)) => {
+            AsymmetricSignature::DeterministicEcdsa {
+                hash_alg: hash.into(),
+            }
+        }
+        (AsymmetricSignature::RsaPkcs1v15SignRaw, Some(hash)) => {
+            return Ok((policy, digest_info(hash, digest)?));
+        }
+        (AsymmetricSignature::RsaPkcs1v15SignRaw, None) | (AsymmetricSignature::EcdsaAny, _) => {
+            policy
+        }
+        _ => {
+            return Err(ToolErrorKind::UnsupportedKeyAlgorithm {
+                key_name: key_name.to_string(),
+                algorithm: format!("{:?}", policy),
+                operation: "signatures".to_string(),
+            }
+            .into());
+        }
+    };
+    Ok((alg, digest.to_vec()))
+}
+
+fn sign_digest(
+    basic_client: &BasicClient,
+    key_name: &str,
+    policy: AsymmetricSignature,
+    hash: Option<Hash>,
+    digest: &[u8],
+    encoding: SignatureEncoding,
+) -> Result<Vec<u8>> {
+    let (alg, input) = signing_operation(key_name, policy, hash, digest)?;
+    info!("Signing data with {:?}...", alg);
+    let mut sig = basic_client.psa_sign_hash(key_name, &input, alg)?;
+    if alg.is_ecc_alg() && encoding == SignatureEncoding::Der {
+        let s = IntegerAsn1::from_bytes_be_unsigned(sig.split_off(sig.len() / 2));
+        sig = picky_asn1_der::to_vec(&EccSignature {
+            r: IntegerAsn1::from_bytes_be_unsigned(sig),
+            s,
+        })
+        .unwrap();
+    }
+
+    Ok(sig)
+}
+
+/// Hashes the data with the given algorithm, locally.
+pub fn hash_data(data: &[u8], alg: Hash) -> Result<Vec<u8>> {
+    Input::from(data).hash(alg)
+}
+
+/// A throwaway key, deleted when dropped.
+#[derive(Debug)]
+pub struct TemporaryKey<'a> {
+    basic_client: &'a BasicClient,
+    name: String,
+}
+
+impl<'a> TemporaryKey<'a> {
+    /// Generates the key as a volatile key: it does not outlive the service if it is not deleted.
+    pub fn generate(
+        basic_client: &'a BasicClient,
+        name: String,
+        mut attributes: Attributes,
+    ) -> Result<Self> {
+        attributes.lifetime = Lifetime::Volatile;
+        basic_client.psa_generate_key(&name, attributes)?;
+        Ok(TemporaryKey { basic_client, name })
+    }
+
+    /// Name of the key.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for TemporaryKey<'_> {
+    fn drop(&mut self) {
+        if let Err(e) = self.basic_client.psa_destroy_key(&self.name) {
+            warn!("Could not delete the key \"{}\": {}", self.name, e);
+        }
+    }
 }
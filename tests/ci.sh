@@ -14,7 +14,8 @@ error_msg () {
 export PARSEC_SERVICE_ENDPOINT="unix:/tmp/parsec.sock"
 export RUST_LOG=error
 
-#TODO: This applies the rcgen patch that exposes the PKCS_RSA_PSS_SHA256 and PKCS_RSA_PSS_SHA384 types. Remove this
+#TODO: This applies the rcgen patch that exposes the PKCS_RSA_PSS_SHA256, PKCS_RSA_PSS_SHA384 and PKCS_RSA_PSS_SHA512
+#      types, as well as the ECDSA types whose hash algorithm differs from the curve strength. Remove this
 #      when the corresponding patch gets merged. Also remove rcgen+0.9.3.patch.
 rustup install 1.77.1 # We know that this version works for patch-crate
 cargo +1.77.1 install patch-crate --version 0.1.9
@@ -82,5 +83,23 @@ fi
 #############
 ./target/debug/parsec-tool --help
 
+# The last build has the spiffe-auth feature: serve a SPIFFE ID from the stand-in Workload API for
+# the JWT-SVID tests, which run if the service enables the JWT-SVID authenticator.
+if ! python3 -c "import grpc" 2>/dev/null; then
+    python3 -m pip install --user grpcio
+fi
+SPIFFE_SOCKET=/tmp/spiffe-workload-api.sock
+export SPIFFE_ID="spiffe://parsec.test/parsec-tool"
+export SPIFFE_ENDPOINT_SOCKET="unix:$SPIFFE_SOCKET"
+rm -f $SPIFFE_SOCKET
+python3 tests/spiffe-workload-api.py $SPIFFE_SOCKET $SPIFFE_ID &
+SPIFFE_PID=$!
+trap "kill $SPIFFE_PID" EXIT
+for _ in $(seq 50); do
+    [ -S $SPIFFE_SOCKET ] && break
+    sleep 0.1
+done
+[ -S $SPIFFE_SOCKET ] || error_msg "the stand-in SPIFFE Workload API did not start"
+
 PARSEC_TOOL="./target/debug/parsec-tool" tests/parsec-cli-tests.sh -d
 PARSEC_TOOL="./target/debug/parsec-tool" tests/parsec-cli-tests.sh -d --rsa-key-size 1024
@@ -45,13 +45,15 @@ delete_key() {
 create_key() {
 # $1 - key type ("RSA" or "ECC")
 # $2 - key name
-# $3 - key usage ("SIGN_PKCS1_V15", "SIGN_PSS" or "OAEP"), only consulted if $1 == "RSA"
+# $3 - key usage ("SIGN_PKCS1_V15", "SIGN_PSS", "SIGN_RAW" or "OAEP"), only consulted if $1 == "RSA"
     KEY="$2"
 
     if [ "$3" = "SIGN_PKCS1_V15" -a "$1" = "RSA" ]; then
         EXTRA_CREATE_KEY_ARGS="--for-signing"
     elif [ "$3" = "SIGN_PSS" -a "$1" = "RSA" ]; then
         EXTRA_CREATE_KEY_ARGS="--for-signing-pss"
+    elif [ "$3" = "SIGN_RAW" -a "$1" = "RSA" ]; then
+        EXTRA_CREATE_KEY_ARGS="--for-signing-raw"
     elif [ "$3" = "OAEP" -a "$1" = "RSA" ]; then
         EXTRA_CREATE_KEY_ARGS="--oaep"
     else
@@ -77,6 +79,201 @@ create_key() {
     run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY >${MY_TMP}/${KEY}.pem
 }
 
+test_endpoint() {
+    echo
+    echo "- Pinging the service with an explicit endpoint"
+    run_cmd env -u PARSEC_SERVICE_ENDPOINT $PARSEC_TOOL --endpoint "$PARSEC_SERVICE_ENDPOINT" ping
+
+    echo
+    echo "- Checking that a wrong endpoint overrides the environment"
+    if $PARSEC_TOOL --endpoint unix:${MY_TMP}/no-parsec.sock ping >/dev/null 2>&1; then
+        echo "Error: ping succeeded with a wrong endpoint"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+}
+
+test_config() {
+    cat >${MY_TMP}/config.toml <<EOF
+[profiles.default]
+endpoint = "unix:${MY_TMP}/no-parsec.sock"
+
+[profiles.test]
+endpoint = "$PARSEC_SERVICE_ENDPOINT"
+output-format = "json"
+EOF
+
+    echo
+    echo "- Pinging the service with a configuration profile"
+    run_cmd env -u PARSEC_SERVICE_ENDPOINT $PARSEC_TOOL --config ${MY_TMP}/config.toml \
+        --profile test ping >${MY_TMP}/ping.json
+    debug cat ${MY_TMP}/ping.json
+    if ! grep -q '"wire_protocol_version"' ${MY_TMP}/ping.json; then
+        echo "Error: the output format of the profile was not used"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+
+    echo
+    echo "- Checking that the endpoint of the command line overrides the profile"
+    run_cmd $PARSEC_TOOL --config ${MY_TMP}/config.toml --endpoint "$PARSEC_SERVICE_ENDPOINT" \
+        ping >/dev/null
+}
+
+test_whoami() {
+    echo
+    echo "- Checking the session information"
+    run_cmd $PARSEC_TOOL whoami >${MY_TMP}/whoami.txt
+    debug cat ${MY_TMP}/whoami.txt
+    run_cmd $PARSEC_TOOL --output-format json session-info >${MY_TMP}/whoami.json
+    debug cat ${MY_TMP}/whoami.json
+    if ! grep -q "^Endpoint: $PARSEC_SERVICE_ENDPOINT$" ${MY_TMP}/whoami.txt; then
+        echo "Error: whoami does not show the endpoint $PARSEC_SERVICE_ENDPOINT"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+    if ! grep -q '"authenticator"' ${MY_TMP}/whoami.json; then
+        echo "Error: session-info does not show the authenticator in JSON"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+}
+
+test_doctor() {
+    # Providers not supporting all the algorithms probed by the doctor are tested with options
+    # disabling those algorithms.
+    if [ -n "$NO_OAEP" ] || [ -n "$NO_PKCS1_V15" ]; then
+        return
+    fi
+
+    echo
+    echo "- Checking the service with the doctor"
+    if [ "$RSA_KEY_SIZE" ]; then
+        DOCTOR_ARGS="--rsa-key-size $RSA_KEY_SIZE"
+    else
+        DOCTOR_ARGS=""
+    fi
+    if [ "$PROVIDER" ]; then
+        DOCTOR_TOOL="$PARSEC_TOOL -p $PROVIDER"
+    else
+        DOCTOR_TOOL="$PARSEC_TOOL"
+    fi
+    run_cmd $DOCTOR_TOOL doctor $DOCTOR_ARGS
+
+    if run_cmd $PARSEC_TOOL list-keys 2>&1 | grep -q "parsec-tool-doctor-"; then
+        echo "Error: the doctor did not delete its keys"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+}
+
+test_bench() {
+    echo
+    echo "- Running a short benchmark"
+    if [ "$RSA_KEY_SIZE" ]; then
+        BENCH_ARGS="--rsa-key-size $RSA_KEY_SIZE"
+    else
+        BENCH_ARGS=""
+    fi
+    if [ "$PROVIDER" ]; then
+        BENCH_TOOL="$PARSEC_TOOL -p $PROVIDER"
+    else
+        BENCH_TOOL="$PARSEC_TOOL"
+    fi
+    run_cmd $BENCH_TOOL --output-format json bench --algorithms random,ecdsa-p256 \
+        --iterations 5 --keygen-iterations 2 $BENCH_ARGS >${MY_TMP}/bench.json
+    debug cat ${MY_TMP}/bench.json
+    if ! grep -q '"ops_per_sec"' ${MY_TMP}/bench.json; then
+        echo "Error: the benchmark did not report any result"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+
+    if run_cmd $PARSEC_TOOL list-keys 2>&1 | grep -q "parsec-tool-bench-"; then
+        echo "Error: the benchmark did not delete its keys"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+}
+
+test_exit_codes() {
+    echo
+    echo "- Checking the exit code of a missing key"
+    $PARSEC_TOOL --error-format json export-public-key --key-name parsec-tool-no-such-key \
+        2>${MY_TMP}/error.json
+    CODE=$?
+    debug cat ${MY_TMP}/error.json
+    if [ $CODE -ne 5 ]; then
+        echo "Error: expected the exit code 5 for a missing key, got $CODE"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+    if ! tail -n 1 ${MY_TMP}/error.json | grep -q '"category":"key-not-found"'; then
+        echo "Error: the JSON error does not report the key-not-found category"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+}
+
+test_spiffe() {
+    # Needs parsec-tool built with the spiffe-auth feature, the JWT-SVID authenticator enabled on
+    # the service and a Workload API serving SPIFFE_ID, like tests/spiffe-workload-api.py.
+    if [ -z "$SPIFFE_ID" ] || [ -z "$SPIFFE_ENDPOINT_SOCKET" ]; then
+        return
+    fi
+    if ! $PARSEC_TOOL list-authenticators 2>/dev/null | grep -q "^ID: 0x04"; then
+        echo
+        echo "- The JWT-SVID authenticator is not enabled, skipping the SPIFFE tests"
+        return
+    fi
+
+    echo
+    echo "- Checking the SPIFFE ID used with the JWT-SVID authenticator"
+    run_cmd env -u SPIFFE_ENDPOINT_SOCKET $PARSEC_TOOL --auth jwt-svid \
+        --spiffe-socket "$SPIFFE_ENDPOINT_SOCKET" whoami >${MY_TMP}/whoami.txt
+    debug cat ${MY_TMP}/whoami.txt
+    if ! grep -q "^SPIFFE ID: $SPIFFE_ID$" ${MY_TMP}/whoami.txt; then
+        echo "Error: the SPIFFE ID is not $SPIFFE_ID"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+}
+
+test_auth() {
+    # Format of list-authenticators output:
+    #ID: 0x01 (Direct authentication)
+    #ID: 0x03 (Unix Peer Credentials authentication)
+    AUTHENTICATORS=$($PARSEC_TOOL list-authenticators 2>/dev/null | grep "^ID:" | cut -f 2 -d ' ')
+
+    for auth in direct:0x01 unix-peer:0x03; do
+        auth_name=${auth%:*}
+        auth_id=${auth#*:}
+        if echo "$AUTHENTICATORS" | grep -q "^$auth_id$"; then
+            echo
+            echo "- Listing keys with the $auth_name authenticator"
+            run_cmd $PARSEC_TOOL --auth $auth_name list-keys >/dev/null
+            if [ "$auth_name" = "direct" ]; then
+                test_app_name
+            fi
+        else
+            echo
+            echo "- Checking that the $auth_name authenticator is refused"
+            if $PARSEC_TOOL --auth $auth_name list-keys >/dev/null 2>&1; then
+                echo "Error: the $auth_name authenticator is not enabled but was accepted"
+                EXIT_CODE=$(($EXIT_CODE+1))
+            fi
+        fi
+    done
+}
+
+test_app_name() {
+    KEY="anta-key-app-name"
+    DIRECT_TOOL="$PARSEC_TOOL --auth direct"
+
+    echo
+    echo "- Checking that a key created under an application name is not listed under another one"
+    run_cmd $DIRECT_TOOL --app-name anta-app-1 create-ecc-key --key-name $KEY
+    if ! run_cmd $DIRECT_TOOL --app-name anta-app-1 list-keys | grep -q "$KEY"; then
+        echo "Error: $KEY is not listed under the application name it was created with"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+    if run_cmd $DIRECT_TOOL --app-name anta-app-2 list-keys | grep -q "$KEY"; then
+        echo "Error: $KEY is listed under another application name"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+    run_cmd $DIRECT_TOOL --app-name anta-app-1 delete-key --key-name $KEY
+}
+
 test_crypto_provider() {
 # $1 - provider ID
 
@@ -97,12 +294,36 @@ test_crypto_provider() {
     if [ -z "$NO_OAEP" ]; then
         test_encryption "OAEP"
         test_decryption "OAEP"
+        test_oaep_label
     fi
 
+    if run_cmd $PARSEC_TOOL_CMD list-opcodes 2>/dev/null | grep -q "PsaRawKeyAgreement"; then
+        test_ecies
+    else
+        echo "This provider doesn't support key agreement"
+    fi
     test_signing "ECC"
+    if [ -z "$NO_RAW_SIGN" ]; then
+        test_raw_signing
+    fi
+    test_export_formats "RSA" "SIGN_PKCS1_V15"
+    test_export_formats "ECC"
+    if [ -n "$SSH_KEYGEN" ]; then
+        # OpenSSH asks for SHA-512 RSA signatures, which SHA-256 signing keys cannot make.
+        if [ -z "$NO_RAW_SIGN" ]; then
+            test_ssh_agent "RSA" "SIGN_RAW"
+        fi
+        test_ssh_agent "ECC"
+    fi
     test_csr "RSA" "SIGN_PKCS1_V15"
     test_csr "RSA" "SIGN_PSS"
     test_csr "ECC"
+    if [ -z "$NO_RAW_SIGN" ]; then
+        test_csr_hash
+    fi
+    test_csr_ecc 384 "Signature: OK"
+    # P-521 signatures cannot be verified by inspect.
+    test_csr_ecc 521 "Signature: not verified (unsupported algorithm)"
     test_rsa_key_bits
     test_rsa_key_bits 1024
 }
@@ -135,6 +356,29 @@ test_encryption() {
             echo "Error: The result is different from the initial string"
             EXIT_CODE=$(($EXIT_CODE+1))
         fi
+
+        echo
+        echo "- Using Parsec to decrypt the result read from the base64 file:"
+        run_cmd $PARSEC_TOOL_CMD decrypt --input-file ${MY_TMP}/${KEY}.enc --key-name $KEY \
+                >${MY_TMP}/${KEY}.enc_str
+        if [ "$(cat ${MY_TMP}/${KEY}.enc_str)" != "$TEST_STR" ]; then
+            echo "Error: The result is different from the initial string"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        if [ "$ALG" = "OAEP" ]; then
+            echo
+            echo "- Encrypting a 64 KiB file in an envelope and decrypting it with Parsec"
+            head -c 65536 /dev/urandom >${MY_TMP}/${KEY}.big
+            run_cmd $PARSEC_TOOL_CMD encrypt --envelope --key-name $KEY \
+                    --input-file ${MY_TMP}/${KEY}.big >${MY_TMP}/${KEY}.env
+            run_cmd $PARSEC_TOOL_CMD decrypt --key-name $KEY \
+                    --input-file ${MY_TMP}/${KEY}.env >${MY_TMP}/${KEY}.big_dec
+            if ! cmp -s ${MY_TMP}/${KEY}.big ${MY_TMP}/${KEY}.big_dec; then
+                echo "Error: The decrypted envelope is different from the initial file"
+                EXIT_CODE=$(($EXIT_CODE+1))
+            fi
+        fi
     fi
 
     delete_key "RSA" $KEY
@@ -181,6 +425,101 @@ test_decryption() {
     delete_key "RSA" $KEY
 }
 
+test_oaep_label() {
+    KEY="anta-key-rsa-label"
+    TEST_STR="$(date) Parsec OAEP label test"
+    LABEL="parsec-label"
+
+    if [ "$RSA_KEY_SIZE" ]; then
+        KEY_LEN="--bits $RSA_KEY_SIZE"
+    else
+        KEY_LEN=""
+    fi
+
+    echo
+    echo "- Creating an RSA OAEP (SHA-384) key and exporting its public part"
+    run_cmd $PARSEC_TOOL_CMD create-rsa-key --key-name $KEY --oaep --oaep-hash sha384 $KEY_LEN
+    run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY >${MY_TMP}/${KEY}.pem
+
+    # If the key was successfully created and exported
+    if [ -s ${MY_TMP}/${KEY}.pem ]; then
+        echo
+        echo "- Encrypting \"$TEST_STR\" string using openssl with the \"$LABEL\" label"
+        printf "$TEST_STR" >${MY_TMP}/${KEY}.test_str
+        printf "$LABEL" >${MY_TMP}/${KEY}.label
+        LABEL_HEX=$(od -A n -t x1 ${MY_TMP}/${KEY}.label | tr -d ' \n')
+        run_cmd $OPENSSL pkeyutl -encrypt -pubin -inkey ${MY_TMP}/${KEY}.pem \
+                                 -pkeyopt rsa_padding_mode:oaep -pkeyopt rsa_oaep_md:sha384 \
+                                 -pkeyopt rsa_oaep_label:$LABEL_HEX \
+                                 -in ${MY_TMP}/${KEY}.test_str -out ${MY_TMP}/${KEY}.bin
+
+        echo
+        echo "- Using Parsec to decrypt the result with the label in hexadecimal"
+        run_cmd $PARSEC_TOOL_CMD decrypt --key-name $KEY --label-hex $LABEL_HEX \
+                --input-file ${MY_TMP}/${KEY}.bin >${MY_TMP}/${KEY}.dec
+        if [ "$(cat ${MY_TMP}/${KEY}.dec)" != "$TEST_STR" ]; then
+            echo "Error: The result is different from the initial string"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        echo
+        echo "- Encrypting \"$TEST_STR\" string to the exported public key, in an envelope"
+        run_cmd $PARSEC_TOOL_CMD encrypt --pubkey ${MY_TMP}/${KEY}.pem --oaep --oaep-hash sha384 \
+                --envelope --label "$LABEL" "$TEST_STR" >${MY_TMP}/${KEY}.env
+        run_cmd $PARSEC_TOOL_CMD decrypt --key-name $KEY --label "$LABEL" \
+                --input-file ${MY_TMP}/${KEY}.env >${MY_TMP}/${KEY}.dec
+        if [ "$(cat ${MY_TMP}/${KEY}.dec)" != "$TEST_STR" ]; then
+            echo "Error: The result is different from the initial string"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        echo
+        echo "- Encrypting and decrypting \"$TEST_STR\" string with Parsec and the label"
+        run_cmd $PARSEC_TOOL_CMD encrypt --key-name $KEY --label "$LABEL" "$TEST_STR" >${MY_TMP}/${KEY}.enc
+        run_cmd $PARSEC_TOOL_CMD decrypt --key-name $KEY --label-file ${MY_TMP}/${KEY}.label \
+                $(cat ${MY_TMP}/${KEY}.enc) >${MY_TMP}/${KEY}.dec
+        if [ "$(cat ${MY_TMP}/${KEY}.dec)" != "$TEST_STR" ]; then
+            echo "Error: The result is different from the initial string"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+    fi
+
+    delete_key "RSA" $KEY
+}
+
+test_ecies() {
+    KEY="anta-key-ecies"
+    TEST_STR="$(date) Parsec ECIES encryption"
+
+    echo
+    echo "- Creating an ECC key agreement key"
+    run_cmd $PARSEC_TOOL_CMD create-ecc-key --for-key-agreement --key-name $KEY
+
+    echo
+    echo "- Encrypting \"$TEST_STR\" string with ECIES and decrypting it with Parsec"
+    run_cmd $PARSEC_TOOL_CMD encrypt --envelope --key-name $KEY "$TEST_STR" >${MY_TMP}/${KEY}.env
+    run_cmd $PARSEC_TOOL_CMD decrypt --key-name $KEY --input-file ${MY_TMP}/${KEY}.env \
+            >${MY_TMP}/${KEY}.dec
+    if [ "$(cat ${MY_TMP}/${KEY}.dec)" != "$TEST_STR" ]; then
+        echo "Error: The result is different from the initial string"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+
+
+    echo
+    echo "- Encrypting \"$TEST_STR\" string with ECIES to the exported public key"
+    run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY >${MY_TMP}/${KEY}.pem
+    run_cmd $PARSEC_TOOL_CMD encrypt --envelope --pubkey ${MY_TMP}/${KEY}.pem "$TEST_STR" >${MY_TMP}/${KEY}.env
+    run_cmd $PARSEC_TOOL_CMD decrypt --key-name $KEY --input-file ${MY_TMP}/${KEY}.env \
+            >${MY_TMP}/${KEY}.dec
+    if [ "$(cat ${MY_TMP}/${KEY}.dec)" != "$TEST_STR" ]; then
+        echo "Error: The result is different from the initial string"
+        EXIT_CODE=$(($EXIT_CODE+1))
+    fi
+
+    delete_key "ECC" $KEY
+}
+
 test_signing() {
 # $1 - key type ("RSA" or "ECC")
 # $2 - RSA scheme ("SIGN_PKCS1_V15" or "SIGN_PSS")
@@ -211,6 +550,208 @@ test_signing() {
         printf "$TEST_STR" >${MY_TMP}/${KEY}.test_str
         run_cmd $OPENSSL dgst -sha256 -verify ${MY_TMP}/${KEY}.pem ${EXTRA_VERIFY_ARGS} \
                               -signature ${MY_TMP}/${KEY}.bin ${MY_TMP}/${KEY}.test_str
+
+        echo
+        echo "- Using Parsec to verify the signature"
+        run_cmd $PARSEC_TOOL_CMD verify "$TEST_STR" --key-name $KEY --signature ${MY_TMP}/${KEY}.sign
+
+        echo
+        echo "- Signing a 16 MiB file, hashed in chunks by parsec-tool"
+        head -c 16777216 /dev/urandom >${MY_TMP}/${KEY}.big
+        run_cmd $PARSEC_TOOL_CMD sign --key-name $KEY --input-file ${MY_TMP}/${KEY}.big >${MY_TMP}/${KEY}.big_sign
+        run_cmd $OPENSSL base64 -d -a -A -in ${MY_TMP}/${KEY}.big_sign -out ${MY_TMP}/${KEY}.big_bin
+        run_cmd $OPENSSL dgst -sha256 -verify ${MY_TMP}/${KEY}.pem ${EXTRA_VERIFY_ARGS} \
+                              -signature ${MY_TMP}/${KEY}.big_bin ${MY_TMP}/${KEY}.big
+        run_cmd $PARSEC_TOOL_CMD verify --key-name $KEY --input-file ${MY_TMP}/${KEY}.big \
+                --signature ${MY_TMP}/${KEY}.big_sign
+
+        echo
+        echo "- Signing the SHA-256 digest of \"$TEST_STR\" string, computed by openssl"
+        DIGEST=$($OPENSSL dgst -sha256 -r ${MY_TMP}/${KEY}.test_str | cut -d ' ' -f 1)
+        run_cmd $PARSEC_TOOL_CMD sign --digest $DIGEST --hash sha256 --key-name $KEY >${MY_TMP}/${KEY}.digest_sign
+        run_cmd $OPENSSL base64 -d -a -A -in ${MY_TMP}/${KEY}.digest_sign -out ${MY_TMP}/${KEY}.digest_bin
+        run_cmd $OPENSSL dgst -sha256 -verify ${MY_TMP}/${KEY}.pem ${EXTRA_VERIFY_ARGS} \
+                              -signature ${MY_TMP}/${KEY}.digest_bin ${MY_TMP}/${KEY}.test_str
+
+        echo
+        echo "- Checking that data cannot be signed with a hash other than the one of the key"
+        $PARSEC_TOOL_CMD sign --hash sha512 --key-name $KEY "$TEST_STR" >/dev/null 2>&1
+        CODE=$?
+        if [ $CODE -ne 8 ]; then
+            echo "Error: expected the exit code 8 for a hash not allowed by the key, got $CODE"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        echo
+        echo "- Checking that the hash algorithm of a COSE signature cannot be chosen"
+        $PARSEC_TOOL_CMD sign --format cose --hash sha256 --key-name $KEY "$TEST_STR" >/dev/null 2>&1
+        CODE=$?
+        if [ $CODE -ne 7 ]; then
+            echo "Error: expected the exit code 7 for a hash given for a COSE signature, got $CODE"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        if [ "$1" = "ECC" ]; then
+            echo
+            echo "- Signing \"$TEST_STR\" string with a raw r||s ECDSA signature"
+            run_cmd $PARSEC_TOOL_CMD sign --sig-format raw --key-name $KEY "$TEST_STR" >${MY_TMP}/${KEY}.raw_sign
+            run_cmd $OPENSSL base64 -d -a -A -in ${MY_TMP}/${KEY}.raw_sign -out ${MY_TMP}/${KEY}.raw_bin
+            # P-256 signatures are two 32 bytes integers
+            if [ "$(wc -c <${MY_TMP}/${KEY}.raw_bin)" -ne 64 ]; then
+                echo "Error: The raw ECDSA signature does not have the expected size"
+                EXIT_CODE=$(($EXIT_CODE+1))
+            fi
+            run_cmd $PARSEC_TOOL_CMD verify --sig-format raw --key-name $KEY \
+                    --signature ${MY_TMP}/${KEY}.raw_sign "$TEST_STR"
+        fi
+
+        echo
+        echo "- Signing \"$TEST_STR\" string in a COSE_Sign1 structure and verifying it with Parsec"
+        run_cmd $PARSEC_TOOL_CMD sign --format cose --kid $KEY --key-name $KEY "$TEST_STR" >${MY_TMP}/${KEY}.cose
+        run_cmd $PARSEC_TOOL_CMD verify --format cose --key-name $KEY --signature ${MY_TMP}/${KEY}.cose "$TEST_STR"
+        $PARSEC_TOOL_CMD verify --format cose --key-name $KEY --signature ${MY_TMP}/${KEY}.cose "other data" 2>/dev/null
+        CODE=$?
+        if [ $CODE -ne 9 ]; then
+            echo "Error: expected the exit code 9 for a COSE signature of other data, got $CODE"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        echo
+        echo "- Signing a JSON Web Token with the created $1 key"
+        run_cmd $PARSEC_TOOL_CMD sign-jwt --key-name $KEY --exp 60 --iat '{"sub":"parsec"}' >${MY_TMP}/${KEY}.jwt
+        debug cat ${MY_TMP}/${KEY}.jwt
+        # The header is 27 bytes long, so its base64url encoding needs no padding
+        cut -d . -f 1 ${MY_TMP}/${KEY}.jwt | tr '_-' '/+' | $OPENSSL base64 -d -A >${MY_TMP}/${KEY}.jwt_header
+        if ! grep -q -e '"alg":"ES256"' -e '"alg":"RS256"' -e '"alg":"PS256"' ${MY_TMP}/${KEY}.jwt_header; then
+            echo "Error: The JWT header does not have the expected algorithm"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        if [ -n "$SSH_KEYGEN" ]; then
+            echo
+            echo "- Signing \"$TEST_STR\" string in the OpenSSH SSHSIG format"
+            run_cmd $PARSEC_TOOL_CMD sign --format sshsig --namespace file --key-name $KEY \
+                    --input-file ${MY_TMP}/${KEY}.test_str >${MY_TMP}/${KEY}.sshsig
+            debug cat ${MY_TMP}/${KEY}.sshsig
+            run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY --format openssh >${MY_TMP}/${KEY}.pub
+
+            echo
+            echo "- Using ssh-keygen and Parsec to verify the SSH signature"
+            run_cmd $SSH_KEYGEN -Y check-novalidate -n file -f ${MY_TMP}/${KEY}.pub \
+                    -s ${MY_TMP}/${KEY}.sshsig <${MY_TMP}/${KEY}.test_str
+            run_cmd $PARSEC_TOOL_CMD verify --format sshsig --namespace file --key-name $KEY \
+                    --input-file ${MY_TMP}/${KEY}.test_str --signature ${MY_TMP}/${KEY}.sshsig
+        fi
+    fi
+
+    delete_key $1 $KEY
+}
+
+test_raw_signing() {
+    KEY="anta-key-sign-raw"
+    TEST_STR="$(date) Parsec raw signature test"
+
+    create_key "RSA" $KEY "SIGN_RAW"
+
+    # If the key was successfully created and exported
+    if [ -s ${MY_TMP}/${KEY}.pem ]; then
+        printf "$TEST_STR" >${MY_TMP}/${KEY}.test_str
+
+        echo
+        echo "- Signing \"$TEST_STR\" string with the raw RSA key and SHA-384"
+        run_cmd $PARSEC_TOOL_CMD sign --hash sha384 --key-name $KEY "$TEST_STR" >${MY_TMP}/${KEY}.sign
+        run_cmd $OPENSSL base64 -d -a -A -in ${MY_TMP}/${KEY}.sign -out ${MY_TMP}/${KEY}.bin
+        run_cmd $OPENSSL dgst -sha384 -verify ${MY_TMP}/${KEY}.pem \
+                              -signature ${MY_TMP}/${KEY}.bin ${MY_TMP}/${KEY}.test_str
+        run_cmd $PARSEC_TOOL_CMD verify --hash sha384 --key-name $KEY \
+                --signature ${MY_TMP}/${KEY}.sign "$TEST_STR"
+
+        echo
+        echo "- Signing the SHA-512 digest of \"$TEST_STR\" string with the raw RSA key"
+        DIGEST=$($OPENSSL dgst -sha512 -r ${MY_TMP}/${KEY}.test_str | cut -d ' ' -f 1)
+        run_cmd $PARSEC_TOOL_CMD sign --digest $DIGEST --hash sha512 --key-name $KEY >${MY_TMP}/${KEY}.digest_sign
+        run_cmd $OPENSSL base64 -d -a -A -in ${MY_TMP}/${KEY}.digest_sign -out ${MY_TMP}/${KEY}.digest_bin
+        run_cmd $OPENSSL dgst -sha512 -verify ${MY_TMP}/${KEY}.pem \
+                              -signature ${MY_TMP}/${KEY}.digest_bin ${MY_TMP}/${KEY}.test_str
+    fi
+
+    delete_key "RSA" $KEY
+}
+
+test_export_formats() {
+# $1 - key type ("RSA" or "ECC")
+# $2 - RSA scheme ("SIGN_PKCS1_V15" or "SIGN_PSS")
+    KEY="anta-key-export"
+
+    create_key $1 $KEY $2
+
+    # If the key was successfully created and exported
+    if [ -s ${MY_TMP}/${KEY}.pem ]; then
+        echo
+        echo "- Exporting the public $1 key in DER format and comparing it with the PEM export"
+        run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY --format der >${MY_TMP}/${KEY}.der
+        run_cmd $OPENSSL pkey -pubin -in ${MY_TMP}/${KEY}.pem -outform der -out ${MY_TMP}/${KEY}.pem.der
+        if ! cmp -s ${MY_TMP}/${KEY}.der ${MY_TMP}/${KEY}.pem.der; then
+            echo "Error: The DER export differs from the PEM export"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        echo
+        echo "- Exporting the public $1 key in OpenSSH and JWK formats"
+        run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY --format openssh >${MY_TMP}/${KEY}.ssh
+        debug cat ${MY_TMP}/${KEY}.ssh
+        if ! grep -q -e "^ssh-rsa " -e "^ecdsa-sha2-nistp" ${MY_TMP}/${KEY}.ssh; then
+            echo "Error: The OpenSSH export is not an authorized_keys line"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+        run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY --format jwk >${MY_TMP}/${KEY}.jwk
+        debug cat ${MY_TMP}/${KEY}.jwk
+        if ! grep -q '"kid"' ${MY_TMP}/${KEY}.jwk; then
+            echo "Error: The JWK export does not contain a thumbprint"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+    fi
+
+    delete_key $1 $KEY
+}
+
+test_ssh_agent() {
+# $1 - key type ("RSA" or "ECC")
+# $2 - RSA scheme ("SIGN_RAW")
+    KEY="anta-key-ssh"
+    TEST_STR="$(date) Parsec SSH agent test"
+
+    create_key $1 $KEY $2
+
+    # If the key was successfully created and exported
+    if [ -s ${MY_TMP}/${KEY}.pem ]; then
+        run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY --format openssh >${MY_TMP}/${KEY}.pub
+
+        echo
+        echo "- Signing \"$TEST_STR\" string with ssh-keygen through the Parsec SSH agent"
+        $PARSEC_TOOL_CMD ssh-agent --socket ${MY_TMP}/agent.sock --key-name $KEY >/dev/null &
+        AGENT_PID=$!
+        sleep 1
+
+        echo "- Checking that the agent socket is private and is not taken over by another agent"
+        if [ "$(stat -c %a ${MY_TMP}/agent.sock)" != "600" ]; then
+            echo "Error: expected the mode 600 for the SSH agent socket"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+        if $PARSEC_TOOL_CMD ssh-agent --socket ${MY_TMP}/agent.sock >/dev/null 2>&1; then
+            echo "Error: a second SSH agent replaced the running one"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        printf "$TEST_STR" >${MY_TMP}/${KEY}.test_str
+        run_cmd env SSH_AUTH_SOCK=${MY_TMP}/agent.sock $SSH_KEYGEN -Y sign -f ${MY_TMP}/${KEY}.pub \
+                -n file ${MY_TMP}/${KEY}.test_str
+        kill $AGENT_PID
+
+        echo
+        echo "- Using ssh-keygen and the exported public $1 key to verify the signature"
+        run_cmd $SSH_KEYGEN -Y check-novalidate -n file -f ${MY_TMP}/${KEY}.pub \
+                -s ${MY_TMP}/${KEY}.test_str.sig <${MY_TMP}/${KEY}.test_str
     fi
 
     delete_key $1 $KEY
@@ -245,11 +786,118 @@ test_csr() {
             echo "Error: The CSR does not contain the serialNumber field of the Distinguished Name"
             EXIT_CODE=$(($EXIT_CODE+1))
         fi
+
+        echo
+        echo "- Using parsec-tool to inspect the CSR and compare it with the test key."
+        run_cmd $PARSEC_TOOL_CMD inspect --key-name $KEY ${MY_TMP}/${KEY}.csr >${MY_TMP}/${KEY}.inspect
+        debug cat ${MY_TMP}/${KEY}.inspect
+
+        if ! grep -q "DNS:${TEST_SAN}" ${MY_TMP}/${KEY}.inspect; then
+            echo "Error: parsec-tool inspect does not show the Subject Alternative Name"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        echo
+        echo "- Issuing a certificate for the CSR with a throwaway openssl CA"
+        run_cmd $OPENSSL req -x509 -newkey rsa:2048 -nodes -subj "/CN=Test CA" \
+                             -keyout ${MY_TMP}/ca.key -out ${MY_TMP}/ca.crt 2>/dev/null
+        run_cmd $OPENSSL x509 -req -in ${MY_TMP}/${KEY}.csr -CA ${MY_TMP}/ca.crt -CAkey ${MY_TMP}/ca.key \
+                              -CAcreateserial -out ${MY_TMP}/${KEY}.crt 2>/dev/null
+
+        echo
+        echo "- Signing a file in a detached CMS signature and verifying it with openssl"
+        date >${MY_TMP}/${KEY}.test_str
+        run_cmd $PARSEC_TOOL_CMD sign --format cms --certificate ${MY_TMP}/${KEY}.crt --key-name $KEY \
+                --input-file ${MY_TMP}/${KEY}.test_str >${MY_TMP}/${KEY}.p7s
+        debug cat ${MY_TMP}/${KEY}.p7s
+        run_cmd $OPENSSL cms -verify -binary -inform PEM -in ${MY_TMP}/${KEY}.p7s \
+                             -content ${MY_TMP}/${KEY}.test_str -CAfile ${MY_TMP}/ca.crt -out /dev/null
     fi
 
     delete_key $1 $KEY
 }
 
+test_csr_hash() {
+    KEY="anta-key-csr-hash"
+    TEST_CN="parallaxsecond.com"
+
+    # Raw signing keys can sign CSRs with any hash.
+    create_key "RSA" $KEY "SIGN_RAW"
+
+    # If the key was successfully created and exported
+    if [ -s ${MY_TMP}/${KEY}.pem ]; then
+        for HASH in sha384 sha512; do
+            echo
+            echo "- Creating a CSR signed with ${HASH} and verifying it with openssl"
+            run_cmd $PARSEC_TOOL_CMD create-csr --cn ${TEST_CN} --hash ${HASH} --key-name $KEY >${MY_TMP}/${KEY}.csr
+            run_cmd $OPENSSL req -text -noout -verify -in ${MY_TMP}/${KEY}.csr >${MY_TMP}/${KEY}.txt
+            debug cat ${MY_TMP}/${KEY}.txt
+
+            if ! grep -q "Signature Algorithm: ${HASH}WithRSAEncryption" ${MY_TMP}/${KEY}.txt; then
+                echo "Error: the CSR is not signed with ${HASH}WithRSAEncryption"
+                EXIT_CODE=$(($EXIT_CODE+1))
+            fi
+
+            echo
+            echo "- Using parsec-tool to inspect the ${HASH} CSR"
+            run_cmd $PARSEC_TOOL_CMD inspect --key-name $KEY ${MY_TMP}/${KEY}.csr >${MY_TMP}/${KEY}.inspect
+            debug cat ${MY_TMP}/${KEY}.inspect
+
+            if ! grep -q "Signature: OK" ${MY_TMP}/${KEY}.inspect; then
+                echo "Error: parsec-tool inspect did not verify the ${HASH} CSR signature"
+                EXIT_CODE=$(($EXIT_CODE+1))
+            fi
+        done
+    fi
+
+    delete_key "RSA" $KEY
+}
+
+test_csr_ecc() {
+# $1 - key size (384 or 521)
+# $2 - expected signature line of inspect
+    KEY="anta-key-csr-p$1"
+    TEST_CN="parallaxsecond.com"
+
+    echo
+    echo "- Creating a P-$1 ECC key and exporting its public part"
+    if ! $PARSEC_TOOL_CMD create-ecc-key --key-name $KEY --bits $1 >/dev/null 2>&1; then
+        echo "This provider doesn't support P-$1 keys"
+        return
+    fi
+    run_cmd $PARSEC_TOOL_CMD export-public-key --key-name $KEY >${MY_TMP}/${KEY}.pem
+
+    # If the key was successfully exported
+    if [ -s ${MY_TMP}/${KEY}.pem ]; then
+        echo
+        echo "- Creating a CSR from the P-$1 key and verifying it with openssl"
+        run_cmd $PARSEC_TOOL_CMD create-csr --cn ${TEST_CN} --key-name $KEY >${MY_TMP}/${KEY}.csr
+        run_cmd $OPENSSL req -text -noout -verify -in ${MY_TMP}/${KEY}.csr >${MY_TMP}/${KEY}.txt
+        debug cat ${MY_TMP}/${KEY}.txt
+
+        echo
+        echo "- Using parsec-tool to inspect the P-$1 CSR and compare it with the key"
+        run_cmd $PARSEC_TOOL_CMD inspect --key-name $KEY ${MY_TMP}/${KEY}.csr >${MY_TMP}/${KEY}.inspect
+        debug cat ${MY_TMP}/${KEY}.inspect
+
+        if ! grep -q "$2" ${MY_TMP}/${KEY}.inspect; then
+            echo "Error: parsec-tool inspect did not print \"$2\" for the P-$1 CSR"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+
+        echo
+        echo "- Checking that a CSR cannot be signed with a hash other than the one of the key"
+        $PARSEC_TOOL_CMD create-csr --cn ${TEST_CN} --hash sha256 --key-name $KEY >/dev/null 2>&1
+        CODE=$?
+        if [ $CODE -ne 8 ]; then
+            echo "Error: expected the exit code 8 for a hash not allowed by the key, got $CODE"
+            EXIT_CODE=$(($EXIT_CODE+1))
+        fi
+    fi
+
+    delete_key "ECC" $KEY
+}
+
 test_rsa_key_bits() {
     KEY="anta-key-rsa-bits"
 
@@ -280,6 +928,7 @@ PROVIDER=
 # Test both RSA PKCS#1 v1.5 (default) and RSA OAEP encryption algorithms
 NO_OAEP=
 NO_PKCS1_V15=
+NO_RAW_SIGN=
 RSA_KEY_SIZE=
 while [ "$#" -gt 0 ]; do
     case "$1" in
@@ -297,6 +946,9 @@ while [ "$#" -gt 0 ]; do
         --no-v1.5 )
             NO_PKCS1_V15="true"
         ;;
+        --no-raw-sign )
+            NO_RAW_SIGN="true"
+        ;;
         --no-pss )
             NO_PSS="true"
         ;;
@@ -312,6 +964,7 @@ Usage: $0 [parameter]
     -N:   Test only the provider with N ID
     --no-oaep: Do not test RSA-OAEP(SHA256) encryption/decryption operations
     --no-v1.5: Do not test RSA-PKCS#1-v1.5 encryption/decryption operations
+    --no-raw-sign: Do not test raw RSA-PKCS#1-v1.5 signing operations
     --rsa-key-size: Perform all RSA operations with the specified key length
 
   Environment variables used if defined:
@@ -321,6 +974,11 @@ Usage: $0 [parameter]
                               default: "which parsec-tool"
     OPENSSL                 - full path to openssl
                               default: "which openssl"
+    SSH_KEYGEN              - full path to ssh-keygen, SSH agent tests are skipped without it
+                              default: "which ssh-keygen"
+    SPIFFE_ENDPOINT_SOCKET  - SPIFFE Workload API socket, SPIFFE tests are skipped without it
+    SPIFFE_ID               - SPIFFE ID served by the Workload API, SPIFFE tests are skipped
+                              without it
 EOF
             exit
         ;;
@@ -331,6 +989,7 @@ done
 PARSEC_SERVICE_ENDPOINT="${PARSEC_SERVICE_ENDPOINT:-unix:/run/parsec/parsec.sock}"
 PARSEC_TOOL="${PARSEC_TOOL:-$(which parsec-tool)}"
 OPENSSL="${OPENSSL:-$(which openssl)}"
+SSH_KEYGEN="${SSH_KEYGEN:-$(which ssh-keygen)}"
 
 if [ -z "$PARSEC_TOOL" ] || [ -z "$OPENSSL" ]; then
     echo "ERROR: Cannot find either parsec-tool or openssl."
@@ -342,6 +1001,14 @@ export RUST_LOG="${RUST_LOG:-info}"
 if ! ping_parsec; then exit 1; fi
 
 EXIT_CODE=0
+test_endpoint
+test_config
+test_auth
+test_whoami
+test_doctor
+test_bench
+test_exit_codes
+test_spiffe
 run_cmd $PARSEC_TOOL list-providers 2>/dev/null | grep "^ID:" | grep -v "0x00" \
         >${MY_TMP}/providers.lst
 
@@ -0,0 +1,115 @@
+#!/usr/bin/env python3
+
+# Copyright 2024 Contributors to the Parsec project.
+# SPDX-License-Identifier: Apache-2.0
+
+# Stand-in SPIFFE Workload API server, serving unsigned JWT-SVIDs for a fixed SPIFFE ID.
+# Only for tests: the tokens are not signed and any token is accepted as valid.
+#
+# Needs the grpcio Python package. The protobuf messages are encoded by hand to avoid generating
+# code from workload.proto.
+#
+# Usage: spiffe-workload-api.py <socket path> <SPIFFE ID>
+
+import base64
+import json
+import sys
+import time
+from concurrent import futures
+
+import grpc
+
+
+def b64url(data):
+    return base64.urlsafe_b64encode(data).rstrip(b"=").decode()
+
+
+def varint(value):
+    out = bytearray()
+    while True:
+        byte = value & 0x7F
+        value >>= 7
+        if value:
+            out.append(byte | 0x80)
+        else:
+            out.append(byte)
+            return bytes(out)
+
+
+def field(number, data):
+    """Encodes a length-delimited field."""
+    if isinstance(data, str):
+        data = data.encode()
+    return varint(number << 3 | 2) + varint(len(data)) + data
+
+
+def parse_fields(data):
+    """Returns the (number, value) pairs of the length-delimited and varint fields."""
+    fields = []
+    i = 0
+    while i < len(data):
+        key, i = read_varint(data, i)
+        number, wire_type = key >> 3, key & 7
+        if wire_type == 2:
+            length, i = read_varint(data, i)
+            fields.append((number, data[i:i + length]))
+            i += length
+        elif wire_type == 0:
+            value, i = read_varint(data, i)
+            fields.append((number, value))
+        else:
+            raise ValueError("unsupported wire type %d" % wire_type)
+    return fields
+
+
+def read_varint(data, i):
+    value = shift = 0
+    while True:
+        byte = data[i]
+        i += 1
+        value |= (byte & 0x7F) << shift
+        shift += 7
+        if not byte & 0x80:
+            return value, i
+
+
+def jwt_svid(spiffe_id, audience):
+    header = {"alg": "ES256", "typ": "JWT", "kid": "stand-in"}
+    claims = {"sub": spiffe_id, "aud": audience, "exp": int(time.time()) + 300}
+    return "%s.%s.%s" % (
+        b64url(json.dumps(header).encode()),
+        b64url(json.dumps(claims).encode()),
+        b64url(b"not signed"),
+    )
+
+
+def main():
+    socket_path, spiffe_id = sys.argv[1], sys.argv[2]
+
+    def fetch_jwt_svid(request, context):
+        # JWTSVIDRequest: repeated string audience = 1
+        audience = [value.decode() for number, value in parse_fields(request) if number == 1]
+        # JWTSVIDResponse: repeated JWTSVID svids = 1, JWTSVID: spiffe_id = 1, svid = 2
+        svid = field(1, spiffe_id) + field(2, jwt_svid(spiffe_id, audience))
+        return field(1, svid)
+
+    def validate_jwt_svid(request, context):
+        # ValidateJWTSVIDResponse: string spiffe_id = 1
+        return field(1, spiffe_id)
+
+    handler = grpc.method_handlers_generic_handler(
+        "SpiffeWorkloadAPI",
+        {
+            "FetchJWTSVID": grpc.unary_unary_rpc_method_handler(fetch_jwt_svid),
+            "ValidateJWTSVID": grpc.unary_unary_rpc_method_handler(validate_jwt_svid),
+        },
+    )
+    server = grpc.server(futures.ThreadPoolExecutor(max_workers=4))
+    server.add_generic_rpc_handlers((handler,))
+    server.add_insecure_port("unix:" + socket_path)
+    server.start()
+    server.wait_for_termination()
+
+
+if __name__ == "__main__":
+    main()